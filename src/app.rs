@@ -1,15 +1,46 @@
+use crate::bookmarks::{
+    Bookmark, BookmarkPrompt, decode_bookmarks, decode_position, encode_bookmarks, encode_position,
+};
+use crate::cue::{CueTrack, TrackSpan, find_cue_track, is_cue_file, read_cue};
+use crate::eq::{EqEditor, EqPreset, decode_eq, encode_eq};
+use crate::filter::{FileFilter, fuzzy_match};
+use crate::keymap::Keymap;
+use crate::library::{Library, LibraryBrowser};
+use crate::loudness::{Loudness, analyze, decode_loudness, encode_loudness};
+use crate::metadata::{
+    TrackMetadata, decode_metadata, encode_metadata, is_audio_file, read_metadata, read_replay_gain,
+};
 use crate::player::probe_duration;
-use std::collections::{HashMap, HashSet};
+use crate::playlist::{PlaylistEntry, is_playlist_file, read_playlist, write_m3u8};
+use crate::queue::{QueuedTrack, RepeatMode};
+use crate::session::Session;
+use crate::theme::Theme;
+use crate::timestretch::SpeedMemory;
+use crate::ui::format_duration;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{self, DirEntry};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+const VOLUME_KEY: &str = "volume";
+const SHUFFLE_KEY: &str = "shuffle";
+const REPEAT_KEY: &str = "repeat";
+const EQ_KEY: &str = "eq";
+const SESSION_KEY: &str = "session";
+/// Only files at least this long get a resume position.
+const RESUME_MIN_DURATION: Duration = Duration::from_secs(10 * 60);
+/// A position this close to either end is not worth resuming from.
+const RESUME_MARGIN: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UiMode {
     Default,
     FullScreenPlayer,
+    Library,
+    Equalizer,
 }
 
 #[derive(Debug, Clone)]
@@ -17,18 +48,58 @@ pub struct FileEntry {
     pub name: String,
     pub path: PathBuf,
     pub is_dir: bool,
+    pub is_playlist: bool,
+    /// Set for a track of a CUE sheet, which shares its file with others.
+    pub cue: Option<CueTrack>,
+}
+
+impl FileEntry {
+    pub fn is_track(&self) -> bool {
+        !self.is_dir && !self.is_playlist
+    }
+
+    pub fn span(&self) -> Option<TrackSpan> {
+        self.cue.as_ref().map(|cue| cue.span)
+    }
 }
 
 #[derive(Debug)]
 pub struct App {
     pub ui_mode: UiMode,
     pub current_path: PathBuf,
+    pub open_playlist: Option<PathBuf>,
     pub entries: Vec<FileEntry>,
     pub selected_index: usize,
     pub status: Option<String>,
+    pub library_browser: LibraryBrowser,
+    pub filter: Option<FileFilter>,
+    pub keymap: Keymap,
+    pub theme: Theme,
+    pub eq: EqEditor,
+    pub speed_memory: SpeedMemory,
+    /// Bookmarks of the playing track, in order of position.
+    pub bookmarks: Vec<Bookmark>,
+    /// Selected row of the bookmark overlay, while it is open.
+    pub bookmark_selection: Option<usize>,
+    /// Whether the overlay listing every key binding is open.
+    pub show_help: bool,
+    pub bookmark_prompt: Option<BookmarkPrompt>,
+    bookmarks_key: Option<String>,
+    themes: Vec<Theme>,
     duration_cache: HashMap<PathBuf, Option<Duration>>,
     duration_rx: Option<Receiver<DurationUpdate>>,
     duration_db: Option<sled::Db>,
+    metadata_cache: HashMap<PathBuf, TrackMetadata>,
+    metadata_rx: Option<Receiver<MetadataUpdate>>,
+    metadata_db: Option<sled::Tree>,
+    settings_db: Option<sled::Tree>,
+    loudness_rx: Option<Receiver<LoudnessUpdate>>,
+    loudness_queue: Arc<Mutex<VecDeque<PathBuf>>>,
+    loudness_db: Option<sled::Tree>,
+    speeds_db: Option<sled::Tree>,
+    positions_db: Option<sled::Tree>,
+    bookmarks_db: Option<sled::Tree>,
+    library: Option<Library>,
 }
 
 #[derive(Debug)]
@@ -37,100 +108,923 @@ struct DurationUpdate {
     duration: Option<Duration>,
 }
 
+#[derive(Debug)]
+struct MetadataUpdate {
+    path: PathBuf,
+    metadata: TrackMetadata,
+}
+
+#[derive(Debug)]
+struct LoudnessUpdate {
+    path: PathBuf,
+    loudness: Option<Loudness>,
+}
+
 impl App {
-    pub fn new() -> Self {
-        let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
-        let duration_db = sled::open(".mp3-tui-cache").ok();
+    pub fn new(start_dir: PathBuf, cache_dir: &Path) -> Self {
+        let duration_db = sled::open(cache_dir).ok();
+        let metadata_db = duration_db
+            .as_ref()
+            .and_then(|db| db.open_tree("metadata").ok());
+        let settings_db = duration_db
+            .as_ref()
+            .and_then(|db| db.open_tree("settings").ok());
+        let loudness_db = duration_db
+            .as_ref()
+            .and_then(|db| db.open_tree("loudness").ok());
+        let speeds_db = duration_db
+            .as_ref()
+            .and_then(|db| db.open_tree("speeds").ok());
+        let positions_db = duration_db
+            .as_ref()
+            .and_then(|db| db.open_tree("positions").ok());
+        let bookmarks_db = duration_db
+            .as_ref()
+            .and_then(|db| db.open_tree("bookmarks").ok());
+        let library = duration_db.as_ref().and_then(Library::open);
         let mut app = Self {
             ui_mode: UiMode::Default,
-            current_path: current_dir,
+            current_path: start_dir,
+            open_playlist: None,
             entries: Vec::new(),
             selected_index: 0,
             status: None,
+            library_browser: LibraryBrowser::default(),
+            filter: None,
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            eq: EqEditor::default(),
+            speed_memory: SpeedMemory::default(),
+            bookmarks: Vec::new(),
+            bookmark_selection: None,
+            show_help: false,
+            bookmark_prompt: None,
+            bookmarks_key: None,
+            themes: Theme::presets(),
             duration_cache: HashMap::new(),
             duration_rx: None,
             duration_db,
+            metadata_cache: HashMap::new(),
+            metadata_rx: None,
+            metadata_db,
+            settings_db,
+            loudness_rx: None,
+            loudness_queue: Arc::new(Mutex::new(VecDeque::new())),
+            loudness_db,
+            speeds_db,
+            positions_db,
+            bookmarks_db,
+            library,
         };
         app.reload();
+        if let Some(library) = &mut app.library
+            && !library.roots().is_empty()
+        {
+            library.rescan();
+        }
         app
     }
 
     pub fn reload(&mut self) {
         self.entries.clear();
 
+        let mut listed_durations = Vec::new();
+        match self.open_playlist.clone() {
+            Some(playlist) => listed_durations = self.list_playlist(&playlist),
+            None => self.list_directory(),
+        }
+
+        if self.entries.is_empty() {
+            self.selected_index = 0;
+            self.duration_rx = None;
+            self.metadata_rx = None;
+            self.sync_folder_db(&HashSet::new());
+            return;
+        }
+
+        if self.selected_index >= self.entries.len() {
+            self.selected_index = self.entries.len() - 1;
+        }
+
+        let folder_audio_paths = self.current_folder_audio_paths();
+        self.sync_folder_db(&folder_audio_paths);
+        self.load_cached_folder_durations(&folder_audio_paths);
+        for (path, duration) in listed_durations {
+            self.duration_cache.entry(path).or_insert(Some(duration));
+        }
+        self.load_cached_folder_metadata(&folder_audio_paths);
+        self.start_metadata_prefetch(&folder_audio_paths);
+        self.start_loudness_analysis(&folder_audio_paths);
+        self.start_duration_prefetch(folder_audio_paths);
+    }
+
+    fn list_directory(&mut self) {
         if let Some(parent) = self.current_path.parent() {
             self.entries.push(FileEntry {
                 name: "..".to_string(),
                 path: parent.to_path_buf(),
                 is_dir: true,
+                is_playlist: false,
+                cue: None,
             });
         }
 
+        let mut cue_sheets = Vec::new();
         if let Ok(read_dir) = fs::read_dir(&self.current_path) {
             for entry in read_dir.filter_map(Result::ok) {
                 if let Ok(metadata) = entry.metadata() {
                     let path = entry.path();
+                    if metadata.is_file() && is_cue_file(&path) {
+                        cue_sheets.push(path);
+                        continue;
+                    }
                     let is_visible_dir = metadata.is_dir() && !Self::is_hidden(&entry);
-                    let is_audio = Self::is_audio_file(&path);
-                    if is_visible_dir || is_audio {
+                    let is_audio = is_audio_file(&path);
+                    let is_playlist = !metadata.is_dir() && is_playlist_file(&path);
+                    if is_visible_dir || is_audio || is_playlist {
                         self.entries.push(FileEntry {
                             name: entry.file_name().to_string_lossy().to_string(),
                             path,
                             is_dir: metadata.is_dir(),
+                            is_playlist,
+                            cue: None,
                         });
                     }
                 }
             }
         }
 
+        for sheet in cue_sheets {
+            self.list_cue_tracks(&sheet);
+        }
+
+        // Stable, so the tracks of a CUE sheet stay in sheet order.
         self.entries.sort_by(|a, b| match (a.is_dir, b.is_dir) {
             (true, false) => std::cmp::Ordering::Less,
             (false, true) => std::cmp::Ordering::Greater,
             _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
         });
+    }
 
-        if self.entries.is_empty() {
-            self.selected_index = 0;
-            self.duration_rx = None;
-            self.sync_folder_db(&HashSet::new());
-            return;
+    /// Replaces the files a CUE sheet splits up with an entry per track.
+    fn list_cue_tracks(&mut self, sheet: &Path) {
+        let tracks = match read_cue(sheet) {
+            Ok(tracks) => tracks,
+            Err(err) => {
+                self.status = Some(format!(
+                    "cue sheet {}: {err}",
+                    sheet.file_name().unwrap_or_default().to_string_lossy()
+                ));
+                return;
+            }
+        };
+        let tracks: Vec<CueTrack> = tracks
+            .into_iter()
+            .filter(|track| track.file.is_file())
+            .collect();
+        self.entries.retain(|entry| {
+            entry.cue.is_some() || !tracks.iter().any(|track| track.file == entry.path)
+        });
+        for track in tracks {
+            self.entries.push(FileEntry {
+                name: track
+                    .file
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                path: track.file.clone(),
+                is_dir: false,
+                is_playlist: false,
+                cue: Some(track),
+            });
         }
+    }
 
-        if self.selected_index >= self.entries.len() {
-            self.selected_index = self.entries.len() - 1;
+    /// Lists a playlist as a virtual folder, in playlist order. Returns the
+    /// durations the playlist itself declares so they can seed the cache.
+    fn list_playlist(&mut self, playlist: &Path) -> Vec<(PathBuf, Duration)> {
+        self.entries.push(FileEntry {
+            name: "..".to_string(),
+            path: self.current_path.clone(),
+            is_dir: true,
+            is_playlist: false,
+            cue: None,
+        });
+
+        let playlist_entries = match read_playlist(playlist) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.status = Some(format!("playlist error: {err}"));
+                return Vec::new();
+            }
+        };
+
+        let mut durations = Vec::new();
+        let mut unresolved = Vec::new();
+        for entry in playlist_entries {
+            if !entry.path.is_file() {
+                unresolved.push(entry.path);
+                continue;
+            }
+            if let Some(duration) = entry.duration {
+                durations.push((entry.path.clone(), duration));
+            }
+            let name = entry.title.unwrap_or_else(|| {
+                entry
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            });
+            self.entries.push(FileEntry {
+                name,
+                path: entry.path,
+                is_dir: false,
+                is_playlist: false,
+                cue: None,
+            });
         }
 
-        let folder_audio_paths = self.current_folder_audio_paths();
-        self.sync_folder_db(&folder_audio_paths);
-        self.load_cached_folder_durations(&folder_audio_paths);
-        self.start_duration_prefetch(folder_audio_paths);
+        if let Some(first) = unresolved.first() {
+            self.status = Some(format!(
+                "{} playlist entries not found, e.g. {}",
+                unresolved.len(),
+                first.display()
+            ));
+        }
+        durations
     }
 
     pub fn selected_entry(&self) -> Option<&FileEntry> {
+        if let Some(filter) = &self.filter
+            && !filter.matches.contains(&self.selected_index)
+        {
+            return None;
+        }
         self.entries.get(self.selected_index)
     }
 
+    /// Selects the entry for `path` if the current listing has one.
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == path) {
+            self.selected_index = index;
+        }
+    }
+
     pub fn move_up(&mut self) {
+        if self.ui_mode == UiMode::Library {
+            self.library_browser.move_up();
+            return;
+        }
+        if let Some(filter) = &self.filter {
+            if let Some(&previous) = filter
+                .matches
+                .iter()
+                .rev()
+                .find(|&&index| index < self.selected_index)
+            {
+                self.selected_index = previous;
+            }
+            return;
+        }
         self.selected_index = self.selected_index.saturating_sub(1);
     }
 
     pub fn move_down(&mut self) {
+        if self.ui_mode == UiMode::Library {
+            self.library_browser.move_down();
+            return;
+        }
+        if let Some(filter) = &self.filter {
+            if let Some(&next) = filter
+                .matches
+                .iter()
+                .find(|&&index| index > self.selected_index)
+            {
+                self.selected_index = next;
+            }
+            return;
+        }
         if !self.entries.is_empty() {
             self.selected_index = (self.selected_index + 1).min(self.entries.len() - 1);
         }
     }
 
+    /// Indices into `entries` of the rows currently shown.
+    pub fn visible_entries(&self) -> Vec<usize> {
+        match &self.filter {
+            Some(filter) => filter.matches.clone(),
+            None => (0..self.entries.len()).collect(),
+        }
+    }
+
+    pub fn is_editing_filter(&self) -> bool {
+        self.filter.as_ref().is_some_and(|filter| filter.editing)
+    }
+
+    /// Opens the filter prompt, keeping the current query if one is applied.
+    pub fn start_filter(&mut self) {
+        let filter = self.filter.get_or_insert_with(FileFilter::default);
+        filter.editing = true;
+        self.refilter();
+    }
+
+    pub fn push_filter_char(&mut self, ch: char) {
+        if let Some(filter) = &mut self.filter {
+            filter.query.push(ch);
+            self.refilter();
+        }
+    }
+
+    pub fn pop_filter_char(&mut self) {
+        if let Some(filter) = &mut self.filter {
+            filter.query.pop();
+            self.refilter();
+        }
+    }
+
+    /// Leaves the prompt but keeps the list filtered. An empty query simply
+    /// closes the filter.
+    pub fn finish_filter(&mut self) {
+        match &mut self.filter {
+            Some(filter) if filter.query.is_empty() => self.filter = None,
+            Some(filter) => filter.editing = false,
+            None => {}
+        }
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+    }
+
+    /// Matches the filter against the shown name, the file name and the tags.
+    /// The selection stays put while it still matches, otherwise it moves to
+    /// the nearest match below it.
+    fn refilter(&mut self) {
+        let Some(filter) = &self.filter else {
+            return;
+        };
+        let query = filter.query.clone();
+        let matches: Vec<usize> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.entry_matches(&query, entry))
+            .map(|(index, _)| index)
+            .collect();
+
+        if !matches.contains(&self.selected_index)
+            && let Some(&nearest) = matches
+                .iter()
+                .find(|&&index| index > self.selected_index)
+                .or(matches.last())
+        {
+            self.selected_index = nearest;
+        }
+        if let Some(filter) = &mut self.filter {
+            filter.matches = matches;
+        }
+    }
+
+    fn entry_matches(&self, query: &str, entry: &FileEntry) -> bool {
+        if fuzzy_match(query, &self.display_name(entry)).is_some()
+            || fuzzy_match(query, &entry.name).is_some()
+        {
+            return true;
+        }
+        let metadata = match &entry.cue {
+            Some(cue) => Some(&cue.metadata),
+            None => self.cached_metadata(&entry.path),
+        };
+        metadata.is_some_and(|metadata| {
+            [&metadata.artist, &metadata.album, &metadata.genre]
+                .into_iter()
+                .flatten()
+                .any(|tag| fuzzy_match(query, tag).is_some())
+        })
+    }
+
     pub fn enter_directory(&mut self, path: PathBuf) {
         self.current_path = path;
+        self.open_playlist = None;
+        self.selected_index = 0;
+        self.filter = None;
+        self.reload();
+    }
+
+    pub fn open_playlist(&mut self, path: PathBuf) {
+        if let Some(parent) = path.parent() {
+            self.current_path = parent.to_path_buf();
+        }
+        self.open_playlist = Some(path);
         self.selected_index = 0;
+        self.filter = None;
+        self.reload();
+    }
+
+    /// Saves `tracks` as an M3U8 file in the current folder, named after
+    /// `name` and never overwriting an existing file.
+    pub fn save_playlist(&mut self, name: &str, tracks: &[QueuedTrack]) {
+        if tracks.is_empty() {
+            self.status = Some("nothing to save".to_string());
+            return;
+        }
+
+        let mut target = self.current_path.join(format!("{name}.m3u8"));
+        let mut suffix = 2;
+        while target.exists() {
+            target = self.current_path.join(format!("{name}-{suffix}.m3u8"));
+            suffix += 1;
+        }
+
+        let entries: Vec<PlaylistEntry> = tracks
+            .iter()
+            .map(|track| PlaylistEntry {
+                path: track.path.clone(),
+                title: track
+                    .metadata
+                    .as_ref()
+                    .or_else(|| self.cached_metadata(&track.path))
+                    .and_then(TrackMetadata::display_title),
+                duration: track.duration.or_else(|| self.cached_duration(&track.path)),
+            })
+            .collect();
+
+        self.status = Some(match write_m3u8(&target, &entries) {
+            Ok(()) => format!("saved {}", target.display()),
+            Err(err) => format!("could not save playlist: {err}"),
+        });
         self.reload();
+        self.refilter();
+    }
+
+    pub fn current_folder_name(&self) -> String {
+        self.current_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "folder".to_string())
+    }
+
+    /// Every audio file in the folder, plus the index of the selected one
+    /// among them.
+    pub fn folder_queue(&self) -> (Vec<QueuedTrack>, usize) {
+        let start = self.entries[..self.selected_index.min(self.entries.len())]
+            .iter()
+            .filter(|entry| entry.is_track())
+            .count();
+        let tracks = self
+            .entries
+            .iter()
+            .filter(|entry| entry.is_track())
+            .map(|entry| self.queued_entry(entry))
+            .collect();
+        (tracks, start)
+    }
+
+    /// Audio files from the selection to the end of the folder, ready to be
+    /// handed to the player queue.
+    pub fn tracks_from_selected(&self) -> Vec<QueuedTrack> {
+        self.entries
+            .iter()
+            .skip(self.selected_index)
+            .filter(|entry| entry.is_track())
+            .map(|entry| self.queued_entry(entry))
+            .collect()
+    }
+
+    /// Queue entries for audio files and playlists named on the command line.
+    /// Playlists are expanded in place; entries whose file is missing are
+    /// skipped.
+    pub fn queued_tracks_for(&self, paths: &[PathBuf]) -> Vec<QueuedTrack> {
+        let mut tracks = Vec::new();
+        for path in paths {
+            if !is_playlist_file(path) {
+                tracks.push(self.queued_track(path));
+                continue;
+            }
+            for entry in read_playlist(path).unwrap_or_default() {
+                if entry.path.is_file() {
+                    let mut track = self.queued_track(&entry.path);
+                    track.duration = track.duration.or(entry.duration);
+                    tracks.push(track);
+                }
+            }
+        }
+        tracks
+    }
+
+    pub fn queued_track(&self, path: &Path) -> QueuedTrack {
+        QueuedTrack {
+            path: path.to_path_buf(),
+            duration: self.cached_duration(path),
+            metadata: self.cached_metadata(path).cloned(),
+            loudness: self.cached_loudness(path),
+            span: None,
+            playback: None,
+        }
+    }
+
+    /// The queue entry for a listed track, limited to its span for a track
+    /// of a CUE sheet.
+    pub fn queued_entry(&self, entry: &FileEntry) -> QueuedTrack {
+        let mut track = self.queued_track(&entry.path);
+        if let Some(cue) = &entry.cue {
+            track.duration = cue.span.length(track.duration);
+            track.metadata = Some(cue.metadata.clone());
+            track.span = Some(cue.span);
+        }
+        track
+    }
+
+    /// Like [`Self::queued_track`] for the track of a CUE sheet that starts
+    /// `span` into `path`, found among the sheets next to it.
+    pub fn queued_span(&self, path: &Path, span: TrackSpan) -> QueuedTrack {
+        let mut track = self.queued_track(path);
+        track.duration = span.length(track.duration);
+        track.metadata = find_cue_track(path, span)
+            .map(|cue| cue.metadata)
+            .or(track.metadata);
+        track.span = Some(span);
+        track
+    }
+
+    /// Measured loudness of `path`, once the background analysis got to it.
+    pub fn cached_loudness(&self, path: &Path) -> Option<Loudness> {
+        let key = path.to_string_lossy().to_string();
+        let raw = self.loudness_db.as_ref()?.get(key.as_bytes()).ok()??;
+        decode_loudness(&raw)
+    }
+
+    pub fn cached_metadata(&self, path: &Path) -> Option<&TrackMetadata> {
+        self.metadata_cache.get(path)
+    }
+
+    /// Tagged "Artist - Title" for audio files, the file name otherwise.
+    /// Tracks of a CUE sheet lead with their number.
+    pub fn display_name(&self, entry: &FileEntry) -> String {
+        if !entry.is_track() {
+            return entry.name.clone();
+        }
+        if let Some(cue) = &entry.cue {
+            let title = cue
+                .metadata
+                .display_title()
+                .unwrap_or_else(|| entry.name.clone());
+            return match cue.metadata.track_number {
+                Some(number) => format!("{number:02}. {title}"),
+                None => title,
+            };
+        }
+        self.cached_metadata(&entry.path)
+            .and_then(TrackMetadata::display_title)
+            .unwrap_or_else(|| entry.name.clone())
     }
 
     pub fn cached_duration(&self, path: &Path) -> Option<Duration> {
         self.duration_cache.get(path).and_then(|value| *value)
     }
 
+    pub fn saved_volume(&self) -> Option<f32> {
+        let raw = self.settings_db.as_ref()?.get(VOLUME_KEY).ok()??;
+        let bytes: [u8; 4] = raw.as_ref().try_into().ok()?;
+        Some(f32::from_le_bytes(bytes)).filter(|volume| volume.is_finite())
+    }
+
+    pub fn save_volume(&self, volume: f32) {
+        let Some(tree) = &self.settings_db else {
+            return;
+        };
+        let _ = tree.insert(VOLUME_KEY, &volume.to_le_bytes());
+    }
+
+    pub fn saved_shuffle(&self) -> bool {
+        let Some(tree) = &self.settings_db else {
+            return false;
+        };
+        matches!(tree.get(SHUFFLE_KEY), Ok(Some(raw)) if raw.as_ref() == [1])
+    }
+
+    pub fn saved_repeat(&self) -> RepeatMode {
+        let Some(tree) = &self.settings_db else {
+            return RepeatMode::Off;
+        };
+        match tree.get(REPEAT_KEY) {
+            Ok(Some(raw)) if raw.as_ref() == [1] => RepeatMode::One,
+            Ok(Some(raw)) if raw.as_ref() == [2] => RepeatMode::All,
+            _ => RepeatMode::Off,
+        }
+    }
+
+    pub fn save_playback_modes(&self, shuffle: bool, repeat: RepeatMode) {
+        let Some(tree) = &self.settings_db else {
+            return;
+        };
+        let repeat_byte: u8 = match repeat {
+            RepeatMode::Off => 0,
+            RepeatMode::One => 1,
+            RepeatMode::All => 2,
+        };
+        let _ = tree.insert(SHUFFLE_KEY, &[u8::from(shuffle)]);
+        let _ = tree.insert(REPEAT_KEY, &[repeat_byte]);
+    }
+
+    pub fn saved_session(&self) -> Option<Session> {
+        let raw = self.settings_db.as_ref()?.get(SESSION_KEY).ok()??;
+        Session::decode(&raw)
+    }
+
+    pub fn save_session(&self, session: &Session) {
+        let Some(tree) = &self.settings_db else {
+            return;
+        };
+        let _ = tree.insert(SESSION_KEY, session.encode());
+    }
+
+    /// Adds the current folder to the library roots, or removes it if it is
+    /// already one, and rescans.
+    pub fn toggle_library_root(&mut self) {
+        let Some(library) = &mut self.library else {
+            self.status = Some("library unavailable: cache database not open".to_string());
+            return;
+        };
+
+        let root = self.current_path.clone();
+        if library.has_root(&root) {
+            library.remove_root(&root);
+            self.status = Some(format!("removed library root {}", root.display()));
+        } else {
+            library.add_root(&root);
+            self.status = Some(format!("added library root {}", root.display()));
+        }
+        self.rescan_library();
+    }
+
+    pub fn rescan_library(&mut self) {
+        let Some(library) = &mut self.library else {
+            return;
+        };
+        if !library.rescan() {
+            self.status = Some("library scan already running".to_string());
+        }
+    }
+
+    /// Switches to the library view with a fresh snapshot of the index.
+    pub fn open_library_view(&mut self) {
+        self.ui_mode = UiMode::Library;
+        match &self.library {
+            Some(library) if library.roots().is_empty() => {
+                self.status = Some("no library roots yet: press L in a music folder".to_string());
+            }
+            Some(library) => self.library_browser.set_tracks(library.tracks()),
+            None => {
+                self.status = Some("library unavailable: cache database not open".to_string());
+            }
+        }
+    }
+
+    /// Handles Enter in the library view. Returns the tracks to queue when a
+    /// track was chosen, with the index to start from.
+    pub fn activate_library_selection(&mut self) -> Option<(Vec<QueuedTrack>, usize)> {
+        let (tracks, start) = self.library_browser.activate()?;
+        let tracks = tracks
+            .into_iter()
+            .map(|track| QueuedTrack {
+                loudness: self.cached_loudness(&track.path),
+                path: track.path,
+                duration: track.duration,
+                metadata: Some(track.metadata),
+                span: None,
+                playback: None,
+            })
+            .collect();
+        Some((tracks, start))
+    }
+
+    /// Sets up the equalizer with the config's presets and restores the
+    /// gains from the last session.
+    pub fn set_eq_presets(&mut self, presets: Vec<EqPreset>) {
+        self.eq = EqEditor::new(presets);
+        if let Some(tree) = &self.settings_db
+            && let Ok(Some(raw)) = tree.get(EQ_KEY)
+            && let Some((name, gains)) = decode_eq(&raw)
+        {
+            self.eq.restore(&name, gains);
+        }
+    }
+
+    pub fn save_eq(&self) {
+        let Some(tree) = &self.settings_db else {
+            return;
+        };
+        let _ = tree.insert(EQ_KEY, encode_eq(&self.eq.name, &self.eq.gains));
+    }
+
+    /// The speed remembered for `path` or its folder, 1.0 when none is, and
+    /// `None` when speeds are not remembered at all.
+    pub fn saved_speed(&self, path: &Path) -> Option<f32> {
+        let key = self.speed_key(path)?;
+        let raw = self
+            .speeds_db
+            .as_ref()
+            .and_then(|tree| tree.get(key.as_bytes()).ok().flatten());
+        Some(
+            raw.and_then(|raw| <[u8; 4]>::try_from(raw.as_ref()).ok())
+                .map(f32::from_le_bytes)
+                .filter(|speed| speed.is_finite())
+                .unwrap_or(1.0),
+        )
+    }
+
+    pub fn save_speed(&self, path: &Path, speed: f32) {
+        let (Some(tree), Some(key)) = (&self.speeds_db, self.speed_key(path)) else {
+            return;
+        };
+        let _ = if speed == 1.0 {
+            tree.remove(key.as_bytes())
+        } else {
+            tree.insert(key.as_bytes(), &speed.to_le_bytes())
+        };
+    }
+
+    fn speed_key(&self, path: &Path) -> Option<String> {
+        let path = match self.speed_memory {
+            SpeedMemory::Off => return None,
+            SpeedMemory::File => path,
+            SpeedMemory::Folder => path.parent()?,
+        };
+        Some(path.to_string_lossy().to_string())
+    }
+
+    /// Where a long file was left off, if it was.
+    pub fn saved_position(&self, path: &Path, span: Option<TrackSpan>) -> Option<Duration> {
+        let raw = self
+            .positions_db
+            .as_ref()?
+            .get(track_key(path, span).as_bytes())
+            .ok()??;
+        decode_position(&raw)
+    }
+
+    /// Remembers `position` in a long file, or forgets it near either end so
+    /// a finished file starts over.
+    pub fn save_position(
+        &self,
+        path: &Path,
+        span: Option<TrackSpan>,
+        position: Duration,
+        duration: Option<Duration>,
+    ) {
+        let Some(tree) = &self.positions_db else {
+            return;
+        };
+        let Some(duration) = duration.filter(|duration| *duration >= RESUME_MIN_DURATION) else {
+            return;
+        };
+        let key = track_key(path, span);
+        let _ = if position < RESUME_MARGIN || position + RESUME_MARGIN > duration {
+            tree.remove(key.as_bytes())
+        } else {
+            tree.insert(key.as_bytes(), &encode_position(position))
+        };
+    }
+
+    /// Loads the bookmarks of the track now playing.
+    pub fn load_bookmarks(&mut self, track: Option<(&Path, Option<TrackSpan>)>) {
+        self.bookmarks_key = track.map(|(path, span)| track_key(path, span));
+        self.bookmarks = self
+            .bookmarks_key
+            .as_ref()
+            .and_then(|key| {
+                let tree = self.bookmarks_db.as_ref()?;
+                let raw = tree.get(key.as_bytes()).ok()??;
+                decode_bookmarks(&raw)
+            })
+            .unwrap_or_default();
+        if let Some(selected) = &mut self.bookmark_selection {
+            *selected = (*selected).min(self.bookmarks.len().saturating_sub(1));
+        }
+    }
+
+    fn store_bookmarks(&self) {
+        let (Some(tree), Some(key)) = (&self.bookmarks_db, &self.bookmarks_key) else {
+            return;
+        };
+        let _ = if self.bookmarks.is_empty() {
+            tree.remove(key.as_bytes())
+        } else {
+            tree.insert(key.as_bytes(), encode_bookmarks(&self.bookmarks))
+        };
+    }
+
+    pub fn start_bookmark_prompt(&mut self, position: Duration) {
+        self.bookmark_prompt = Some(BookmarkPrompt {
+            position,
+            name: String::new(),
+        });
+    }
+
+    /// Adds the bookmark being named. An empty name becomes "bookmark N".
+    pub fn finish_bookmark_prompt(&mut self) {
+        let Some(prompt) = self.bookmark_prompt.take() else {
+            return;
+        };
+        if self.bookmarks_key.is_none() {
+            return;
+        }
+        let name = match prompt.name.trim() {
+            "" => format!("bookmark {}", self.bookmarks.len() + 1),
+            name => name.to_string(),
+        };
+        let index = self
+            .bookmarks
+            .partition_point(|bookmark| bookmark.position <= prompt.position);
+        self.status = Some(format!(
+            "bookmarked {name} at {}",
+            format_duration(prompt.position)
+        ));
+        self.bookmarks.insert(
+            index,
+            Bookmark {
+                name,
+                position: prompt.position,
+            },
+        );
+        self.store_bookmarks();
+    }
+
+    pub fn toggle_bookmark_list(&mut self) {
+        self.bookmark_selection = match self.bookmark_selection {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    pub fn selected_bookmark(&self) -> Option<&Bookmark> {
+        self.bookmarks.get(self.bookmark_selection?)
+    }
+
+    pub fn move_bookmark_selection(&mut self, down: bool) {
+        if let Some(selected) = &mut self.bookmark_selection {
+            *selected = if down {
+                (*selected + 1).min(self.bookmarks.len().saturating_sub(1))
+            } else {
+                selected.saturating_sub(1)
+            };
+        }
+    }
+
+    pub fn delete_selected_bookmark(&mut self) {
+        let Some(selected) = self
+            .bookmark_selection
+            .filter(|selected| *selected < self.bookmarks.len())
+        else {
+            return;
+        };
+        let removed = self.bookmarks.remove(selected);
+        self.bookmark_selection = Some(selected.min(self.bookmarks.len().saturating_sub(1)));
+        self.status = Some(format!("deleted bookmark {}", removed.name));
+        self.store_bookmarks();
+    }
+
+    pub fn cycle_eq_preset(&mut self) {
+        self.eq.cycle_preset();
+        self.status = Some(format!("eq: {}", self.eq.name));
+    }
+
+    /// Makes `theme` current. A customised theme from the config joins the
+    /// presets in the cycle so it can be got back to.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.themes = Theme::presets();
+        if !self.themes.contains(&theme) {
+            self.themes.insert(0, theme.clone());
+        }
+        self.theme = theme;
+    }
+
+    pub fn cycle_theme(&mut self) {
+        let current = self
+            .themes
+            .iter()
+            .position(|theme| *theme == self.theme)
+            .unwrap_or(0);
+        self.theme = self.themes[(current + 1) % self.themes.len()].clone();
+        self.status = Some(format!("theme: {}", self.theme.name));
+    }
+
+    pub fn library_status(&self) -> Option<String> {
+        self.library.as_ref()?.progress_text()
+    }
+
     pub fn update_background_jobs(&mut self) {
+        if let Some(library) = &mut self.library
+            && library.update()
+            && self.ui_mode == UiMode::Library
+        {
+            self.library_browser.set_tracks(library.tracks());
+        }
+
         let mut disconnect = false;
 
         if let Some(rx) = &self.duration_rx {
@@ -153,18 +1047,65 @@ impl App {
         if disconnect {
             self.duration_rx = None;
         }
-    }
 
-    pub fn is_audio_file(path: &Path) -> bool {
-        path.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| {
-                matches!(
-                    ext.to_ascii_lowercase().as_str(),
-                    "mp3" | "wav" | "flac" | "ogg" | "m4a" | "aac"
-                )
-            })
-            .unwrap_or(false)
+        let mut disconnect = false;
+        let mut received_metadata = false;
+
+        if let Some(rx) = &self.metadata_rx {
+            loop {
+                match rx.try_recv() {
+                    Ok(update) => {
+                        received_metadata = true;
+                        self.write_metadata_to_db(&update.path, &update.metadata);
+                        self.metadata_cache.insert(update.path, update.metadata);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        disconnect = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if disconnect {
+            self.metadata_rx = None;
+        }
+        if received_metadata {
+            self.refilter();
+        }
+
+        let mut disconnect = false;
+
+        if let Some(rx) = &self.loudness_rx {
+            loop {
+                match rx.try_recv() {
+                    Ok(update) => {
+                        if let Some(tree) = &self.loudness_db {
+                            let key = update.path.to_string_lossy().to_string();
+                            let _ = tree.insert(key.as_bytes(), encode_loudness(update.loudness));
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        disconnect = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if disconnect {
+            self.loudness_rx = None;
+            // Paths queued after the worker found the queue empty.
+            let pending = self
+                .loudness_queue
+                .lock()
+                .is_ok_and(|queue| !queue.is_empty());
+            if pending {
+                self.spawn_loudness_worker();
+            }
+        }
     }
 
     fn is_hidden(entry: &DirEntry) -> bool {
@@ -198,34 +1139,123 @@ impl App {
         self.duration_rx = Some(rx);
     }
 
+    fn start_metadata_prefetch(&mut self, folder_audio_paths: &HashSet<PathBuf>) {
+        let paths_to_scan: Vec<PathBuf> = folder_audio_paths
+            .iter()
+            .filter(|path| !self.metadata_cache.contains_key(*path))
+            .cloned()
+            .collect();
+
+        if paths_to_scan.is_empty() {
+            self.metadata_rx = None;
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for path in paths_to_scan {
+                let metadata = read_metadata(&path).unwrap_or_default();
+                if tx.send(MetadataUpdate { path, metadata }).is_err() {
+                    break;
+                }
+            }
+        });
+        self.metadata_rx = Some(rx);
+    }
+
+    fn is_analyzing_loudness(&self) -> bool {
+        self.loudness_rx.is_some()
+    }
+
+    /// Queues files for loudness measurement, the current folder first and
+    /// then the rest of the library. While the analyzer is running only the
+    /// folder is moved to the front of its queue.
+    fn start_loudness_analysis(&mut self, folder_audio_paths: &HashSet<PathBuf>) {
+        if self.loudness_db.is_none() {
+            return;
+        }
+        let Ok(mut queue) = self.loudness_queue.lock() else {
+            return;
+        };
+
+        let mut folder: Vec<PathBuf> = folder_audio_paths.iter().cloned().collect();
+        folder.sort();
+        let running = self.is_analyzing_loudness();
+        if running {
+            queue.retain(|path| !folder_audio_paths.contains(path));
+        } else {
+            queue.clear();
+            if let Some(library) = &self.library {
+                queue.extend(
+                    library
+                        .paths()
+                        .into_iter()
+                        .filter(|path| !folder_audio_paths.contains(path)),
+                );
+            }
+        }
+        for path in folder.into_iter().rev() {
+            queue.push_front(path);
+        }
+        drop(queue);
+
+        if !running {
+            self.spawn_loudness_worker();
+        }
+    }
+
+    /// Measures queued files without ReplayGain tags until the queue runs
+    /// dry. Files already in the cache, measured or found to be tagged, are
+    /// skipped.
+    fn spawn_loudness_worker(&mut self) {
+        let Some(tree) = self.loudness_db.clone() else {
+            return;
+        };
+        let queue = Arc::clone(&self.loudness_queue);
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            while let Some(path) = queue.lock().ok().and_then(|mut queue| queue.pop_front()) {
+                let key = path.to_string_lossy().to_string();
+                if matches!(tree.contains_key(key.as_bytes()), Ok(true)) {
+                    continue;
+                }
+                let loudness = match read_replay_gain(&path) {
+                    Some(_) => None,
+                    None => analyze(&path),
+                };
+                if tx.send(LoudnessUpdate { path, loudness }).is_err() {
+                    break;
+                }
+            }
+        });
+        self.loudness_rx = Some(rx);
+    }
+
     fn current_folder_audio_paths(&self) -> HashSet<PathBuf> {
         self.entries
             .iter()
-            .filter(|entry| !entry.is_dir)
+            .filter(|entry| entry.is_track())
             .map(|entry| entry.path.clone())
             .collect()
     }
 
     fn sync_folder_db(&mut self, folder_audio_paths: &HashSet<PathBuf>) {
-        let Some(db) = &self.duration_db else {
+        if self.open_playlist.is_some() {
             return;
-        };
-
-        let mut stale_keys = Vec::new();
-        for item in db.iter().flatten() {
-            let (key, _) = item;
-            let key_path = PathBuf::from(String::from_utf8_lossy(&key).to_string());
-            if key_path.parent() == Some(self.current_path.as_path())
-                && !folder_audio_paths.contains(&key_path)
-            {
-                stale_keys.push(key);
-            }
         }
-
-        for key in stale_keys {
-            let _ = db.remove(key);
+        if let Some(db) = &self.duration_db {
+            remove_stale_folder_keys(db, &self.current_path, folder_audio_paths);
+        }
+        if let Some(tree) = &self.metadata_db {
+            remove_stale_folder_keys(tree, &self.current_path, folder_audio_paths);
+        }
+        if let Some(tree) = &self.loudness_db {
+            remove_stale_folder_keys(tree, &self.current_path, folder_audio_paths);
+        }
+        if let Some(db) = &self.duration_db {
+            let _ = db.flush();
         }
-        let _ = db.flush();
     }
 
     fn load_cached_folder_durations(&mut self, folder_audio_paths: &HashSet<PathBuf>) {
@@ -242,6 +1272,29 @@ impl App {
         }
     }
 
+    fn load_cached_folder_metadata(&mut self, folder_audio_paths: &HashSet<PathBuf>) {
+        let Some(tree) = &self.metadata_db else {
+            return;
+        };
+
+        for path in folder_audio_paths {
+            let key = path.to_string_lossy().to_string();
+            if let Ok(Some(raw)) = tree.get(key.as_bytes())
+                && let Some(metadata) = decode_metadata(&raw)
+            {
+                self.metadata_cache.insert(path.clone(), metadata);
+            }
+        }
+    }
+
+    fn write_metadata_to_db(&self, path: &Path, metadata: &TrackMetadata) {
+        let Some(tree) = &self.metadata_db else {
+            return;
+        };
+        let key = path.to_string_lossy().to_string();
+        let _ = tree.insert(key.as_bytes(), encode_metadata(metadata));
+    }
+
     fn write_duration_to_db(&self, path: &Path, duration: Option<Duration>) {
         let Some(db) = &self.duration_db else {
             return;
@@ -251,6 +1304,25 @@ impl App {
     }
 }
 
+fn remove_stale_folder_keys(
+    tree: &sled::Tree,
+    folder: &Path,
+    folder_audio_paths: &HashSet<PathBuf>,
+) {
+    let mut stale_keys = Vec::new();
+    for item in tree.iter().flatten() {
+        let (key, _) = item;
+        let key_path = PathBuf::from(String::from_utf8_lossy(&key).to_string());
+        if key_path.parent() == Some(folder) && !folder_audio_paths.contains(&key_path) {
+            stale_keys.push(key);
+        }
+    }
+
+    for key in stale_keys {
+        let _ = tree.remove(key);
+    }
+}
+
 fn encode_duration(duration: Option<Duration>) -> Vec<u8> {
     match duration {
         Some(value) => {
@@ -272,3 +1344,13 @@ fn decode_duration(raw: &[u8]) -> Option<Duration> {
     millis_bytes.copy_from_slice(&raw[1..9]);
     Some(Duration::from_millis(u64::from_le_bytes(millis_bytes)))
 }
+
+/// Key for what is remembered per track: the path, plus where the track
+/// starts for one of several in a file.
+fn track_key(path: &Path, span: Option<TrackSpan>) -> String {
+    let path = path.to_string_lossy();
+    match span {
+        Some(span) => format!("{path}#{}", span.start.as_millis()),
+        None => path.to_string(),
+    }
+}
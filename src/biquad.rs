@@ -0,0 +1,53 @@
+use std::f64::consts::PI;
+
+/// Direct form II transposed biquad with `a0` normalised to 1.
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// A peaking filter from the RBJ audio EQ cookbook: `gain_db` at
+    /// `frequency`, falling off over a width set by `q`.
+    pub fn peaking(frequency: f64, gain_db: f64, q: f64, rate: f64) -> Self {
+        let amplitude = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha / amplitude;
+        Self::new(
+            [
+                (1.0 + alpha * amplitude) / a0,
+                -2.0 * cos / a0,
+                (1.0 - alpha * amplitude) / a0,
+            ],
+            [-2.0 * cos / a0, (1.0 - alpha / amplitude) / a0],
+        )
+    }
+
+    /// Takes over `other`'s coefficients while keeping this filter's state,
+    /// so a change of setting does not click.
+    pub fn retune(&mut self, other: &Self) {
+        self.b = other.b;
+        self.a = other.a;
+    }
+
+    pub fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.z1;
+        self.z1 = self.b[1] * input - self.a[0] * output + self.z2;
+        self.z2 = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use crate::metadata::{decode_text, encode_text};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub position: Duration,
+}
+
+/// A bookmark being named, at the position it was added.
+#[derive(Debug, Clone)]
+pub struct BookmarkPrompt {
+    pub position: Duration,
+    pub name: String,
+}
+
+pub fn encode_bookmarks(bookmarks: &[Bookmark]) -> Vec<u8> {
+    let mut bytes = vec![1];
+    for bookmark in bookmarks {
+        bytes.extend_from_slice(&(bookmark.position.as_millis() as u64).to_le_bytes());
+        encode_text(&mut bytes, Some(&bookmark.name));
+    }
+    bytes
+}
+
+pub fn decode_bookmarks(raw: &[u8]) -> Option<Vec<Bookmark>> {
+    let (&version, mut rest) = raw.split_first()?;
+    if version != 1 {
+        return None;
+    }
+    let mut bookmarks = Vec::new();
+    while !rest.is_empty() {
+        let (millis, tail) = rest.split_first_chunk::<8>()?;
+        rest = tail;
+        let name = decode_text(&mut rest)??;
+        bookmarks.push(Bookmark {
+            name,
+            position: Duration::from_millis(u64::from_le_bytes(*millis)),
+        });
+    }
+    Some(bookmarks)
+}
+
+pub fn encode_position(position: Duration) -> [u8; 8] {
+    (position.as_millis() as u64).to_le_bytes()
+}
+
+pub fn decode_position(raw: &[u8]) -> Option<Duration> {
+    let millis = u64::from_le_bytes(raw.try_into().ok()?);
+    Some(Duration::from_millis(millis))
+}
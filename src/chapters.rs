@@ -0,0 +1,423 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+/// Boxes bigger than this are not read into memory looking for chapters.
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+/// More samples than this in a chapter track are not taken for chapters.
+const MAX_CHAPTER_SAMPLES: usize = 10_000;
+/// Nero `chpl` chapter starts count units of 100 ns.
+const CHPL_UNITS_PER_SECOND: u64 = 10_000_000;
+
+/// A chapter marked in the file. It runs until the next one starts, or to
+/// the end of the track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+}
+
+/// Reads the chapters of `path` in order: ID3 `CHAP` frames, or in MP4
+/// files the chapter track, falling back to a Nero `chpl` list. Empty when
+/// the file has none.
+pub fn read_chapters(path: &Path) -> Vec<Chapter> {
+    let Ok(mut file) = File::open(path) else {
+        return Vec::new();
+    };
+    let mut header = [0; 10];
+    if file.read_exact(&mut header).is_err() {
+        return Vec::new();
+    }
+    let chapters = if header.starts_with(b"ID3") {
+        read_id3_chapters(&mut file, &header)
+    } else if &header[4..8] == b"ftyp" {
+        read_mp4_chapters(&mut file)
+    } else {
+        None
+    };
+
+    let mut chapters = chapters.unwrap_or_default();
+    chapters.sort_by_key(|chapter| chapter.start);
+    for (index, chapter) in chapters.iter_mut().enumerate() {
+        if chapter.title.trim().is_empty() {
+            chapter.title = format!("Chapter {}", index + 1);
+        }
+    }
+    chapters
+}
+
+/// Index of the chapter playing at `position`.
+pub fn chapter_at(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters
+        .iter()
+        .rposition(|chapter| chapter.start <= position)
+}
+
+fn read_id3_chapters(file: &mut File, header: &[u8; 10]) -> Option<Vec<Chapter>> {
+    let version = header[3];
+    if version != 3 && version != 4 {
+        return None;
+    }
+    let flags = header[5];
+    let mut tag = vec![0; syncsafe(&header[6..10]) as usize];
+    file.read_exact(&mut tag).ok()?;
+    // Version 4 marks unsynchronisation on each frame instead.
+    if version == 3 && flags & 0x80 != 0 {
+        tag = remove_unsynchronisation(&tag);
+    }
+    let mut frames = tag.as_slice();
+    if flags & 0x40 != 0 {
+        let skip = if version == 3 {
+            be_u32(frames, 0)? as usize + 4
+        } else {
+            syncsafe(frames.get(..4)?) as usize
+        };
+        frames = frames.get(skip..)?;
+    }
+
+    let chapters = id3_frames(frames, version)
+        .into_iter()
+        .filter(|(id, _)| id == b"CHAP")
+        .filter_map(|(_, body)| {
+            // Element ID, then start and end times and byte offsets.
+            let id_end = body.iter().position(|&byte| byte == 0)?;
+            let start = be_u32(&body, id_end + 1)?;
+            let title = id3_frames(body.get(id_end + 17..)?, version)
+                .into_iter()
+                .find(|(id, _)| id == b"TIT2")
+                .map(|(_, text)| decode_id3_text(&text))
+                .unwrap_or_default();
+            Some(Chapter {
+                title,
+                start: Duration::from_millis(u64::from(start)),
+            })
+        })
+        .collect();
+    Some(chapters)
+}
+
+/// The frames in `data` as (ID, body) pairs, skipping compressed and
+/// encrypted ones.
+fn id3_frames(mut data: &[u8], version: u8) -> Vec<([u8; 4], Vec<u8>)> {
+    let mut frames = Vec::new();
+    while data.len() >= 10 && data[0] != 0 {
+        let id: [u8; 4] = data[..4].try_into().unwrap_or_default();
+        let size = if version == 4 {
+            syncsafe(&data[4..8])
+        } else {
+            be_u32(data, 4).unwrap_or(0)
+        } as usize;
+        let format_flags = data[9];
+        let Some(body) = data.get(10..10 + size) else {
+            break;
+        };
+        data = &data[10 + size..];
+
+        let (extra, skip, unsynchronised) = if version == 4 {
+            let grouping = usize::from(format_flags & 0x40 != 0);
+            let length = if format_flags & 0x01 != 0 { 4 } else { 0 };
+            (
+                grouping + length,
+                format_flags & 0x0c != 0,
+                format_flags & 0x02 != 0,
+            )
+        } else {
+            let grouping = usize::from(format_flags & 0x20 != 0);
+            (grouping, format_flags & 0xc0 != 0, false)
+        };
+        if skip {
+            continue;
+        }
+        let Some(body) = body.get(extra..) else {
+            continue;
+        };
+        let body = if unsynchronised {
+            remove_unsynchronisation(body)
+        } else {
+            body.to_vec()
+        };
+        frames.push((id, body));
+    }
+    frames
+}
+
+/// Text frames start with an encoding byte; only the first of several
+/// NUL-separated values is kept.
+fn decode_id3_text(body: &[u8]) -> String {
+    let Some((&encoding, text)) = body.split_first() else {
+        return String::new();
+    };
+    let text = match encoding {
+        0 => text.iter().map(|&byte| char::from(byte)).collect(),
+        1 | 2 => decode_utf16(text, encoding == 2),
+        _ => String::from_utf8_lossy(text).to_string(),
+    };
+    text.split('\0')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// UTF-16, byte order from its BOM when there is one.
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> String {
+    let (big_endian, bytes) = match bytes {
+        [0xfe, 0xff, rest @ ..] => (true, rest),
+        [0xff, 0xfe, rest @ ..] => (false, rest),
+        _ => (big_endian, bytes),
+    };
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Undoes the `FF 00` escaping that keeps tag bytes from looking like MPEG
+/// sync words.
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    for (index, &byte) in data.iter().enumerate() {
+        if byte == 0 && index > 0 && data[index - 1] == 0xff {
+            continue;
+        }
+        output.push(byte);
+    }
+    output
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 7) | u32::from(byte & 0x7f))
+}
+
+fn read_mp4_chapters(file: &mut File) -> Option<Vec<Chapter>> {
+    let moov = read_top_level_box(file, b"moov")?;
+    let tracks: Vec<&[u8]> = mp4_boxes(&moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .map(|(_, body)| body)
+        .collect();
+
+    let chapter_ids: Vec<u32> = tracks
+        .iter()
+        .filter_map(|track| find_box(track, &[b"tref", b"chap"]))
+        .flat_map(|chap| chap.chunks_exact(4))
+        .filter_map(|id| be_u32(id, 0))
+        .collect();
+    for track in &tracks {
+        let is_chapter_track = find_box(track, &[b"tkhd"])
+            .and_then(|tkhd| versioned_u32(tkhd, 12, 20))
+            .is_some_and(|id| chapter_ids.contains(&id));
+        if !is_chapter_track {
+            continue;
+        }
+        if let Some(chapters) = read_chapter_track(file, track)
+            && !chapters.is_empty()
+        {
+            return Some(chapters);
+        }
+    }
+
+    find_box(&moov, &[b"udta", b"chpl"]).and_then(parse_chpl)
+}
+
+/// Reads the text samples of a QuickTime chapter track: each is a 16-bit
+/// length and the title, lasting until the next.
+fn read_chapter_track(file: &mut File, track: &[u8]) -> Option<Vec<Chapter>> {
+    let timescale = find_box(track, &[b"mdia", b"mdhd"])
+        .and_then(|mdhd| versioned_u32(mdhd, 12, 20))
+        .filter(|&timescale| timescale > 0)?;
+    let table = find_box(track, &[b"mdia", b"minf", b"stbl"])?;
+
+    let stts = table_entries(find_box(table, &[b"stts"])?, 8)?;
+    let mut starts = Vec::new();
+    let mut time = 0u64;
+    'entries: for entry in stts {
+        let (count, delta) = (be_u32(entry, 0)?, be_u32(entry, 4)?);
+        for _ in 0..count {
+            if starts.len() >= MAX_CHAPTER_SAMPLES {
+                break 'entries;
+            }
+            starts.push(time);
+            time = time.checked_add(u64::from(delta))?;
+        }
+    }
+
+    let stsz = find_box(table, &[b"stsz"])?;
+    let (fixed_size, count) = (be_u32(stsz, 4)?, be_u32(stsz, 8)? as usize);
+    let sizes: Vec<u32> = if fixed_size != 0 {
+        vec![fixed_size; count.min(starts.len())]
+    } else {
+        (0..count.min(starts.len()))
+            .map(|index| be_u32(stsz, 12 + index * 4))
+            .collect::<Option<_>>()?
+    };
+
+    let chunk_offsets: Vec<u64> = if let Some(stco) = find_box(table, &[b"stco"]) {
+        table_entries(stco, 4)?
+            .map(|entry| be_u32(entry, 0).map(u64::from))
+            .collect::<Option<_>>()?
+    } else {
+        table_entries(find_box(table, &[b"co64"])?, 8)?
+            .map(|entry| be_u64(entry, 0))
+            .collect::<Option<_>>()?
+    };
+    let samples_per_chunk: Vec<(u32, u32)> = table_entries(find_box(table, &[b"stsc"])?, 12)?
+        .map(|entry| Some((be_u32(entry, 0)?, be_u32(entry, 4)?)))
+        .collect::<Option<_>>()?;
+
+    let mut offsets = Vec::with_capacity(sizes.len());
+    for (index, &chunk_offset) in chunk_offsets.iter().enumerate() {
+        let chunk = index as u32 + 1;
+        let in_chunk = samples_per_chunk
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= chunk)
+            .map_or(1, |(_, samples)| *samples);
+        let mut offset = chunk_offset;
+        for _ in 0..in_chunk {
+            let Some(&size) = sizes.get(offsets.len()) else {
+                break;
+            };
+            offsets.push(offset);
+            offset += u64::from(size);
+        }
+    }
+
+    let mut chapters = Vec::with_capacity(offsets.len());
+    for ((offset, size), start) in offsets.into_iter().zip(sizes).zip(starts) {
+        let mut sample = vec![0; size.min(4096) as usize];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut sample).ok()?;
+        let length = sample.get(..2).map_or(0, |length| {
+            usize::from(u16::from_be_bytes([length[0], length[1]]))
+        });
+        let text = sample.get(2..2 + length).unwrap_or_default();
+        let title = if text.starts_with(&[0xfe, 0xff]) || text.starts_with(&[0xff, 0xfe]) {
+            decode_utf16(text, true)
+        } else {
+            String::from_utf8_lossy(text).to_string()
+        };
+        chapters.push(Chapter {
+            title: title.trim().to_string(),
+            start: Duration::from_secs_f64(start as f64 / f64::from(timescale)),
+        });
+    }
+    Some(chapters)
+}
+
+/// The Nero chapter list: a count, then for each a start in 100 ns units and
+/// a length-prefixed title.
+fn parse_chpl(chpl: &[u8]) -> Option<Vec<Chapter>> {
+    let version = *chpl.first()?;
+    let mut offset = if version == 0 { 4 } else { 8 };
+    let count = *chpl.get(offset)?;
+    offset += 1;
+    let mut chapters = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let start = be_u64(chpl, offset)?;
+        let length = usize::from(*chpl.get(offset + 8)?);
+        let title = chpl.get(offset + 9..offset + 9 + length)?;
+        offset += 9 + length;
+        chapters.push(Chapter {
+            title: String::from_utf8_lossy(title).trim().to_string(),
+            start: Duration::from_nanos(
+                start.saturating_mul(1_000_000_000 / CHPL_UNITS_PER_SECOND),
+            ),
+        });
+    }
+    Some(chapters)
+}
+
+/// Finds a top-level box by walking the box headers, and reads its body.
+fn read_top_level_box(file: &mut File, kind: &[u8; 4]) -> Option<Vec<u8>> {
+    let length = file.metadata().ok()?.len();
+    let mut offset: u64 = 0;
+    while offset < length && length - offset >= 8 {
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        let mut header = [0; 16];
+        file.read_exact(&mut header[..8]).ok()?;
+        let (size, header_size) = match be_u32(&header, 0)? {
+            0 => (length - offset, 8),
+            1 => {
+                file.read_exact(&mut header[8..]).ok()?;
+                (be_u64(&header, 8)?, 16)
+            }
+            size => (u64::from(size), 8),
+        };
+        if size < header_size {
+            return None;
+        }
+        if &header[4..8] == kind {
+            if size - header_size > MAX_MOOV_SIZE {
+                return None;
+            }
+            let mut body = vec![0; (size - header_size) as usize];
+            file.read_exact(&mut body).ok()?;
+            return Some(body);
+        }
+        offset = offset.checked_add(size)?;
+    }
+    None
+}
+
+/// The boxes directly inside `data`, as (type, body) pairs.
+fn mp4_boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+        let (size, header_size) = match be_u32(data, 0)? {
+            0 => (data.len(), 8),
+            1 => (usize::try_from(be_u64(data, 8)?).ok()?, 16),
+            size => (size as usize, 8),
+        };
+        let body = data.get(header_size..size)?;
+        data = &data[size..];
+        Some((kind, body))
+    })
+}
+
+/// Follows `path` down through nested boxes.
+fn find_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |data, kind| {
+        mp4_boxes(data)
+            .find(|(found, _)| found == *kind)
+            .map(|(_, body)| body)
+    })
+}
+
+/// The fixed-size entries of a sample table box, after its version, flags
+/// and entry count.
+fn table_entries(data: &[u8], entry_size: usize) -> Option<std::slice::ChunksExact<'_, u8>> {
+    let count = be_u32(data, 4)? as usize;
+    let entries = data.get(8..8 + count.checked_mul(entry_size)?)?;
+    Some(entries.chunks_exact(entry_size))
+}
+
+/// A field that sits further in when the box is version 1, whose times are
+/// 64 bits wide.
+fn versioned_u32(data: &[u8], offset: usize, version_1_offset: usize) -> Option<u32> {
+    match data.first()? {
+        1 => be_u32(data, version_1_offset),
+        _ => be_u32(data, offset),
+    }
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
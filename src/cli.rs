@@ -0,0 +1,94 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: mp3-tui [OPTIONS] [PATH...]
+
+A directory opens the browser there. Audio files and playlists are queued
+and start playing right away.
+
+Options:
+      --volume <PERCENT>   Start at this volume (0-100)
+      --shuffle            Start with shuffle on
+      --no-resume          Start afresh instead of where the last session left off
+      --config <FILE>      Read settings from FILE instead of the default
+      --cache-dir <DIR>    Keep the tag, duration and library cache in DIR
+  -h, --help               Print this help
+  -V, --version            Print the version";
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub paths: Vec<PathBuf>,
+    /// Between 0.0 and 1.0.
+    pub volume: Option<f32>,
+    pub shuffle: bool,
+    pub no_resume: bool,
+    pub config: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Run(Args),
+    Help,
+    Version,
+}
+
+/// Parses the arguments after the program name. Options take their value
+/// either as the next argument or after `=`; everything after `--` is a path.
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Command, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    let mut only_paths = false;
+
+    while let Some(arg) = args.next() {
+        let text = arg.to_string_lossy();
+        if only_paths || !text.starts_with('-') || text == "-" {
+            parsed.paths.push(PathBuf::from(arg));
+            continue;
+        }
+
+        let (name, inline_value) = match text.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(OsString::from(value))),
+            None => (text.to_string(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{name} needs a value"))
+        };
+
+        match name.as_str() {
+            "--" | "-h" | "--help" | "-V" | "--version" | "--shuffle" | "--no-resume"
+                if inline_value.is_some() =>
+            {
+                return Err(format!("unknown option `{text}`"));
+            }
+            "--" => only_paths = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--shuffle" => parsed.shuffle = true,
+            "--no-resume" => parsed.no_resume = true,
+            "--volume" => {
+                let raw = value("--volume")?;
+                let percent = raw
+                    .to_str()
+                    .and_then(|raw| raw.trim_end_matches('%').parse::<f32>().ok())
+                    .filter(|percent| (0.0..=100.0).contains(percent))
+                    .ok_or_else(|| {
+                        format!(
+                            "--volume expects a number from 0 to 100, got `{}`",
+                            raw.to_string_lossy()
+                        )
+                    })?;
+                parsed.volume = Some(percent / 100.0);
+            }
+            "--config" => parsed.config = Some(PathBuf::from(value("--config")?)),
+            "--cache-dir" => parsed.cache_dir = Some(PathBuf::from(value("--cache-dir")?)),
+            _ => return Err(format!("unknown option `{name}`")),
+        }
+    }
+
+    Ok(Command::Run(parsed))
+}
@@ -0,0 +1,233 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::eq::{BAND_COUNT, EqPreset, MAX_GAIN_DB};
+use crate::keymap::Keymap;
+use crate::replaygain::{ReplayGainMode, ReplayGainSettings};
+use crate::theme::Theme;
+use crate::timestretch::SpeedMemory;
+
+/// Settings read from `config.toml` in the XDG config directory
+/// (`~/.config/mp3-tui/config.toml` by default). A missing file means
+/// defaults everywhere.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub keymap: Keymap,
+    pub theme: Theme,
+    /// Overlap between tracks from different albums; zero means no crossfade.
+    pub crossfade: Duration,
+    pub replay_gain: ReplayGainSettings,
+    pub preserve_pitch: bool,
+    pub speed_memory: SpeedMemory,
+    /// Whether to pick up where the last session left off.
+    pub resume: bool,
+    /// Equalizer presets from `[eq.presets]`, on top of the built-in ones.
+    pub eq_presets: Vec<EqPreset>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            crossfade: Duration::ZERO,
+            replay_gain: ReplayGainSettings::default(),
+            preserve_pitch: true,
+            speed_memory: SpeedMemory::default(),
+            resume: true,
+            eq_presets: Vec::new(),
+        }
+    }
+}
+
+const MAX_CROSSFADE_SECS: f64 = 30.0;
+const MAX_PREAMP_DB: f64 = 15.0;
+
+/// Every problem found in the config file, so they can be fixed in one go.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config file {}:", self.path.display())?;
+        for problem in &self.problems {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("mp3-tui").join("config.toml"))
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(ConfigError {
+                    path: path.to_path_buf(),
+                    problems: vec![err.to_string()],
+                });
+            }
+        };
+
+        let mut problems = Vec::new();
+        let config = Self::parse(&text, &mut problems);
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError {
+                path: path.to_path_buf(),
+                problems,
+            })
+        }
+    }
+
+    fn parse(text: &str, problems: &mut Vec<String>) -> Self {
+        let table = match text.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(err) => {
+                problems.push(err.to_string().trim_end().to_string());
+                return Self::default();
+            }
+        };
+
+        let mut config = Self::default();
+        for (section, value) in &table {
+            match (section.as_str(), value) {
+                ("keys", toml::Value::Table(keys)) => {
+                    config.keymap = Keymap::from_config(keys, problems);
+                }
+                ("theme", toml::Value::Table(theme)) => {
+                    config.theme = Theme::from_config(theme, problems);
+                }
+                ("playback", toml::Value::Table(playback)) => {
+                    config.parse_playback(playback, problems);
+                }
+                ("eq", toml::Value::Table(eq)) => config.parse_eq(eq, problems),
+                ("keys" | "theme" | "playback" | "eq", _) => {
+                    problems.push(format!("{section}: expected a table"))
+                }
+                (other, _) => problems.push(format!("{other}: unknown setting")),
+            }
+        }
+        config
+    }
+
+    fn parse_eq(&mut self, table: &toml::Table, problems: &mut Vec<String>) {
+        for (key, value) in table {
+            let ("presets", toml::Value::Table(presets)) = (key.as_str(), value) else {
+                match key.as_str() {
+                    "presets" => problems.push("eq.presets: expected a table".to_string()),
+                    other => problems.push(format!("eq.{other}: unknown setting")),
+                }
+                continue;
+            };
+            for (name, gains) in presets {
+                let gains: Option<Vec<f32>> = gains.as_array().and_then(|gains| {
+                    gains
+                        .iter()
+                        .map(|gain| {
+                            let db = match gain {
+                                toml::Value::Integer(db) => *db as f32,
+                                toml::Value::Float(db) => *db as f32,
+                                _ => return None,
+                            };
+                            (db.abs() <= MAX_GAIN_DB).then_some(db)
+                        })
+                        .collect()
+                });
+                match gains.and_then(|gains| <[f32; BAND_COUNT]>::try_from(gains).ok()) {
+                    Some(gains) => self.eq_presets.push(EqPreset {
+                        name: name.clone(),
+                        gains,
+                    }),
+                    None => problems.push(format!(
+                        "eq.presets.{name}: expected {BAND_COUNT} gains in dB from -{MAX_GAIN_DB} to {MAX_GAIN_DB}"
+                    )),
+                }
+            }
+        }
+    }
+
+    fn parse_playback(&mut self, table: &toml::Table, problems: &mut Vec<String>) {
+        for (key, value) in table {
+            match key.as_str() {
+                "crossfade" => {
+                    let seconds = match value {
+                        toml::Value::Integer(seconds) => Some(*seconds as f64),
+                        toml::Value::Float(seconds) => Some(*seconds),
+                        _ => None,
+                    };
+                    match seconds.filter(|seconds| (0.0..=MAX_CROSSFADE_SECS).contains(seconds)) {
+                        Some(seconds) => self.crossfade = Duration::from_secs_f64(seconds),
+                        None => problems.push(format!(
+                            "playback.crossfade: expected a number of seconds from 0 to {MAX_CROSSFADE_SECS}"
+                        )),
+                    }
+                }
+                "replaygain" => match value.as_str().and_then(ReplayGainMode::from_name) {
+                    Some(mode) => self.replay_gain.mode = mode,
+                    None => problems.push(format!(
+                        "playback.replaygain: expected one of {}",
+                        ReplayGainMode::NAMES.join(", ")
+                    )),
+                },
+                "preamp" => {
+                    let db = match value {
+                        toml::Value::Integer(db) => Some(*db as f64),
+                        toml::Value::Float(db) => Some(*db),
+                        _ => None,
+                    };
+                    match db.filter(|db| db.abs() <= MAX_PREAMP_DB) {
+                        Some(db) => self.replay_gain.preamp = db as f32,
+                        None => problems.push(format!(
+                            "playback.preamp: expected a number of dB from -{MAX_PREAMP_DB} to {MAX_PREAMP_DB}"
+                        )),
+                    }
+                }
+                "prevent-clipping" => match value.as_bool() {
+                    Some(prevent) => self.replay_gain.prevent_clipping = prevent,
+                    None => problems
+                        .push("playback.prevent-clipping: expected true or false".to_string()),
+                },
+                "preserve-pitch" => match value.as_bool() {
+                    Some(preserve) => self.preserve_pitch = preserve,
+                    None => {
+                        problems.push("playback.preserve-pitch: expected true or false".to_string())
+                    }
+                },
+                "remember-speed" => match value.as_str().and_then(SpeedMemory::from_name) {
+                    Some(memory) => self.speed_memory = memory,
+                    None => problems.push(format!(
+                        "playback.remember-speed: expected one of {}",
+                        SpeedMemory::NAMES.join(", ")
+                    )),
+                },
+                "resume" => match value.as_bool() {
+                    Some(resume) => self.resume = resume,
+                    None => problems.push("playback.resume: expected true or false".to_string()),
+                },
+                other => problems.push(format!("playback.{other}: unknown setting")),
+            }
+        }
+    }
+}
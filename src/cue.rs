@@ -0,0 +1,533 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+use crate::metadata::{TrackMetadata, is_audio_file};
+
+/// CUE sheet timestamps count frames of 1/75 s, as on a CD.
+const FRAMES_PER_SECOND: u64 = 75;
+
+/// The part of a file one track occupies. An open end runs to the end of
+/// the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrackSpan {
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+impl TrackSpan {
+    /// Length of the span within a file of `total` length.
+    pub fn length(&self, total: Option<Duration>) -> Option<Duration> {
+        self.end.or(total).map(|end| end.saturating_sub(self.start))
+    }
+}
+
+/// One track of a CUE sheet: where it lies in its file and its tags.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub file: PathBuf,
+    pub span: TrackSpan,
+    pub metadata: TrackMetadata,
+}
+
+pub fn is_cue_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// Reads the audio tracks of a CUE sheet. Each ends where the next one in
+/// the same file starts; the last track of a file runs to its end.
+pub fn read_cue(path: &Path) -> Result<Vec<CueTrack>, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    let folder = path.parent().unwrap_or(Path::new("."));
+    parse_cue(&String::from_utf8_lossy(&bytes), folder)
+}
+
+/// Parses the text of a CUE sheet whose files are named relative to
+/// `folder`.
+fn parse_cue(text: &str, folder: &Path) -> Result<Vec<CueTrack>, String> {
+    let mut album = TrackMetadata::default();
+    let mut file: Option<PathBuf> = None;
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    // The audio track being read, until its INDEX 01 gives it a start.
+    let mut pending: Option<(PathBuf, TrackMetadata, Option<Duration>)> = None;
+    // Set from the first TRACK on, so that the tags of a data track are
+    // not taken for the album's.
+    let mut in_track = false;
+
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let (command, rest) = split_word(line.trim());
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                // A track whose INDEX 01 is still to come starts in this file.
+                if matches!(pending, Some((_, _, Some(_)))) {
+                    finish_track(&mut tracks, pending.take());
+                }
+                file = Some(resolve_file(folder, &unquote(strip_file_type(rest))));
+            }
+            "TRACK" => {
+                finish_track(&mut tracks, pending.take());
+                in_track = true;
+                let (number, kind) = split_word(rest);
+                if !kind.eq_ignore_ascii_case("AUDIO") {
+                    continue;
+                }
+                let Some(file) = &file else {
+                    return Err(format!("track {number} comes before any FILE"));
+                };
+                let metadata = TrackMetadata {
+                    track_number: number.parse().ok(),
+                    artist: album.artist.clone(),
+                    album: album.title.clone(),
+                    year: album.year,
+                    genre: album.genre.clone(),
+                    ..TrackMetadata::default()
+                };
+                pending = Some((file.clone(), metadata, None));
+            }
+            "INDEX" => {
+                let (number, time) = split_word(rest);
+                if let Some((track_file, _, start)) = &mut pending
+                    && number.parse::<u32>() == Ok(1)
+                {
+                    if let Some(file) = &file {
+                        track_file.clone_from(file);
+                    }
+                    *start =
+                        Some(parse_time(time).ok_or_else(|| format!("bad INDEX time `{time}`"))?);
+                }
+            }
+            "TITLE" => match &mut pending {
+                Some((_, metadata, _)) => metadata.title = Some(unquote(rest)),
+                None if in_track => {}
+                None => album.title = Some(unquote(rest)),
+            },
+            "PERFORMER" => match &mut pending {
+                Some((_, metadata, _)) => metadata.artist = Some(unquote(rest)),
+                None if in_track => {}
+                None => album.artist = Some(unquote(rest)),
+            },
+            "REM" if !in_track => {
+                let (field, value) = split_word(rest);
+                match field.to_ascii_uppercase().as_str() {
+                    "GENRE" => album.genre = Some(unquote(value)),
+                    "DATE" => album.year = value.get(..4).and_then(|year| year.parse().ok()),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    finish_track(&mut tracks, pending);
+
+    for index in 1..tracks.len() {
+        if tracks[index].file == tracks[index - 1].file {
+            tracks[index - 1].span.end = Some(tracks[index].span.start);
+        }
+    }
+    if tracks.is_empty() {
+        return Err("no audio tracks".to_string());
+    }
+    Ok(tracks)
+}
+
+/// The track starting `span` into `path` in one of the CUE sheets next to it.
+pub fn find_cue_track(path: &Path, span: TrackSpan) -> Option<CueTrack> {
+    fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|sheet| is_cue_file(sheet))
+        .filter_map(|sheet| read_cue(&sheet).ok())
+        .flatten()
+        .find(|track| track.file == path && track.span.start == span.start)
+}
+
+fn finish_track(
+    tracks: &mut Vec<CueTrack>,
+    pending: Option<(PathBuf, TrackMetadata, Option<Duration>)>,
+) {
+    if let Some((file, metadata, Some(start))) = pending {
+        tracks.push(CueTrack {
+            file,
+            span: TrackSpan { start, end: None },
+            metadata,
+        });
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// `"name.flac" WAVE` → `"name.flac"`: drops the trailing file type.
+fn strip_file_type(text: &str) -> &str {
+    if text.starts_with('"') {
+        return text.rfind('"').map_or(text, |end| &text[..=end]);
+    }
+    match text.rsplit_once(char::is_whitespace) {
+        Some((name, _)) => name.trim(),
+        None => text,
+    }
+}
+
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+        .to_string()
+}
+
+/// Sheets often name the WAV the rip started as; fall back to a file with
+/// the same stem that is still there, such as the FLAC it was encoded to.
+fn resolve_file(folder: &Path, name: &str) -> PathBuf {
+    let path = folder.join(name);
+    if path.is_file() {
+        return path;
+    }
+    let Some(stem) = path.file_stem() else {
+        return path;
+    };
+    fs::read_dir(folder)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|candidate| candidate.file_stem() == Some(stem) && is_audio_file(candidate))
+        .unwrap_or(path)
+}
+
+/// `mm:ss:ff`, where minutes may go past 99.
+fn parse_time(text: &str) -> Option<Duration> {
+    let mut parts = text.split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return None;
+    }
+    Some(
+        Duration::from_secs(minutes * 60 + seconds)
+            + Duration::from_secs_f64(frames as f64 / FRAMES_PER_SECOND as f64),
+    )
+}
+
+/// Plays only a span of its source. Positions, seeks and the length are all
+/// relative to the start of the span; without one it passes everything on.
+pub struct Span<S> {
+    input: S,
+    start: Duration,
+    length: Option<Duration>,
+    /// Samples (not frames) left before the span ends.
+    remaining: Option<u64>,
+    /// Samples still to drop before the span starts, when the input could
+    /// not seek there.
+    skip: u64,
+}
+
+impl<S: Source> Span<S> {
+    pub fn new(mut input: S, span: Option<TrackSpan>) -> Self {
+        let Some(span) = span else {
+            return Self {
+                input,
+                start: Duration::ZERO,
+                length: None,
+                remaining: None,
+                skip: 0,
+            };
+        };
+
+        let skip = if !span.start.is_zero() && input.try_seek(span.start).is_err() {
+            samples_in(&input, span.start)
+        } else {
+            0
+        };
+        let length = span.end.map(|end| end.saturating_sub(span.start));
+        Self {
+            remaining: length.map(|length| samples_in(&input, length)),
+            skip,
+            input,
+            start: span.start,
+            length,
+        }
+    }
+}
+
+fn samples_in<S: Source>(input: &S, duration: Duration) -> u64 {
+    let frames = (duration.as_secs_f64() * f64::from(input.sample_rate())).round() as u64;
+    frames * u64::from(input.channels().max(1))
+}
+
+impl<S: Source> Iterator for Span<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        while self.skip > 0 {
+            self.skip -= 1;
+            self.input.next()?;
+        }
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
+                return None;
+            }
+            *remaining -= 1;
+        }
+        self.input.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.input.size_hint();
+        match self.remaining {
+            Some(remaining) => {
+                let remaining = usize::try_from(remaining).unwrap_or(usize::MAX);
+                (
+                    lower.min(remaining),
+                    Some(upper.map_or(remaining, |upper| upper.min(remaining))),
+                )
+            }
+            None => (lower, upper),
+        }
+    }
+}
+
+impl<S: Source> Source for Span<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        let span = self.input.current_span_len();
+        match self.remaining {
+            Some(remaining) => {
+                let remaining = usize::try_from(remaining).unwrap_or(usize::MAX);
+                Some(span.map_or(remaining, |span| span.min(remaining)))
+            }
+            None => span,
+        }
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.length.or_else(|| {
+            self.input
+                .total_duration()
+                .map(|total| total.saturating_sub(self.start))
+        })
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(self.start + pos)?;
+        self.skip = 0;
+        if let Some(length) = self.length {
+            self.remaining = Some(samples_in(&self.input, length.saturating_sub(pos)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<CueTrack> {
+        parse_cue(text, Path::new("/nonexistent")).unwrap()
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn titles(tracks: &[CueTrack]) -> Vec<&str> {
+        tracks
+            .iter()
+            .map(|track| track.metadata.title.as_deref().unwrap_or(""))
+            .collect()
+    }
+
+    #[test]
+    fn tracks_end_where_the_next_starts_and_the_last_runs_to_the_end() {
+        let tracks = parse(
+            "PERFORMER \"Band\"\n\
+             TITLE \"Album\"\n\
+             REM DATE 1999\n\
+             FILE \"album.flac\" WAVE\n\
+             TRACK 01 AUDIO\n\
+             TITLE \"One\"\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             TITLE \"Two\"\n\
+             PERFORMER \"Guest\"\n\
+             INDEX 01 03:20:00\n",
+        );
+        assert_eq!(titles(&tracks), ["One", "Two"]);
+        assert_eq!(
+            tracks[0].span,
+            TrackSpan {
+                start: secs(0),
+                end: Some(secs(200))
+            }
+        );
+        assert_eq!(
+            tracks[1].span,
+            TrackSpan {
+                start: secs(200),
+                end: None
+            }
+        );
+        assert_eq!(tracks[0].metadata.artist.as_deref(), Some("Band"));
+        assert_eq!(tracks[1].metadata.artist.as_deref(), Some("Guest"));
+        assert_eq!(tracks[1].metadata.album.as_deref(), Some("Album"));
+        assert_eq!(tracks[1].metadata.year, Some(1999));
+        assert_eq!(tracks[1].metadata.track_number, Some(2));
+        assert_eq!(tracks[0].file, Path::new("/nonexistent/album.flac"));
+    }
+
+    #[test]
+    fn each_file_ends_its_own_tracks() {
+        let tracks = parse(
+            "FILE \"a.wav\" WAVE\n\
+             TRACK 01 AUDIO\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             INDEX 01 01:00:00\n\
+             FILE \"b.wav\" WAVE\n\
+             TRACK 03 AUDIO\n\
+             INDEX 01 00:00:00\n",
+        );
+        let files: Vec<_> = tracks.iter().map(|track| track.file.clone()).collect();
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("/nonexistent/a.wav"),
+                PathBuf::from("/nonexistent/a.wav"),
+                PathBuf::from("/nonexistent/b.wav"),
+            ]
+        );
+        assert_eq!(
+            tracks[1].span,
+            TrackSpan {
+                start: secs(60),
+                end: None
+            }
+        );
+        assert_eq!(
+            tracks[2].span,
+            TrackSpan {
+                start: secs(0),
+                end: None
+            }
+        );
+    }
+
+    #[test]
+    fn a_track_starts_in_the_file_its_index_01_follows() {
+        let tracks = parse(
+            "FILE \"a.wav\" WAVE\n\
+             TRACK 01 AUDIO\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             TITLE \"Two\"\n\
+             INDEX 00 04:00:00\n\
+             FILE \"b.wav\" WAVE\n\
+             INDEX 01 00:00:00\n\
+             TRACK 03 AUDIO\n\
+             INDEX 01 02:00:00\n",
+        );
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(
+            tracks[0].span,
+            TrackSpan {
+                start: secs(0),
+                end: None
+            }
+        );
+        assert_eq!(tracks[1].metadata.title.as_deref(), Some("Two"));
+        assert_eq!(tracks[1].file, Path::new("/nonexistent/b.wav"));
+        assert_eq!(
+            tracks[1].span,
+            TrackSpan {
+                start: secs(0),
+                end: Some(secs(120))
+            }
+        );
+    }
+
+    #[test]
+    fn index_00_pregap_does_not_move_the_start() {
+        let tracks = parse(
+            "FILE \"a.wav\" WAVE\n\
+             TRACK 01 AUDIO\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             INDEX 00 02:58:00\n\
+             INDEX 01 03:00:00\n",
+        );
+        assert_eq!(tracks[0].span.end, Some(secs(180)));
+        assert_eq!(tracks[1].span.start, secs(180));
+    }
+
+    #[test]
+    fn data_tracks_are_skipped_without_taking_their_tags_for_the_album() {
+        let tracks = parse(
+            "TITLE \"Album\"\n\
+             FILE \"a.bin\" BINARY\n\
+             TRACK 01 MODE1/2352\n\
+             TITLE \"Data\"\n\
+             PERFORMER \"Nobody\"\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             TITLE \"Song\"\n\
+             INDEX 01 01:00:00\n",
+        );
+        assert_eq!(titles(&tracks), ["Song"]);
+        assert_eq!(tracks[0].metadata.album.as_deref(), Some("Album"));
+        assert_eq!(tracks[0].metadata.artist, None);
+    }
+
+    #[test]
+    fn sheets_without_audio_or_files_are_errors() {
+        assert!(parse_cue("TITLE \"Empty\"\n", Path::new("/")).is_err());
+        assert!(parse_cue("TRACK 01 AUDIO\nINDEX 01 00:00:00\n", Path::new("/")).is_err());
+        assert!(
+            parse_cue(
+                "FILE \"a.wav\" WAVE\nTRACK 01 AUDIO\nINDEX 01 1:2\n",
+                Path::new("/")
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn times_count_frames_of_a_75th_of_a_second() {
+        assert_eq!(parse_time("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_time("01:02:00"), Some(secs(62)));
+        assert_eq!(
+            parse_time("00:01:15"),
+            Some(secs(1) + Duration::from_secs_f64(0.2))
+        );
+        assert_eq!(parse_time("120:00:00"), Some(secs(7200)));
+    }
+
+    #[test]
+    fn malformed_times_are_rejected() {
+        for text in [
+            "",
+            "1:2",
+            "00:60:00",
+            "00:00:75",
+            "aa:00:00",
+            "00:00:00:00",
+            "-1:00:00",
+        ] {
+            assert_eq!(parse_time(text), None, "{text}");
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+use crate::biquad::Biquad;
+
+pub const BAND_COUNT: usize = 10;
+/// Centre frequencies of the ISO octave bands, in Hz.
+pub const BAND_FREQUENCIES: [f32; BAND_COUNT] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1_000.0, 2_000.0, 4_000.0, 8_000.0, 16_000.0,
+];
+pub const MAX_GAIN_DB: f32 = 12.0;
+pub const GAIN_STEP_DB: f32 = 1.0;
+/// About one octave wide, so neighbouring bands overlap smoothly.
+const BAND_Q: f64 = 1.41;
+
+pub type EqGains = [f32; BAND_COUNT];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqPreset {
+    pub name: String,
+    pub gains: EqGains,
+}
+
+impl EqPreset {
+    fn new(name: &str, gains: EqGains) -> Self {
+        Self {
+            name: name.to_string(),
+            gains,
+        }
+    }
+}
+
+pub fn builtin_presets() -> Vec<EqPreset> {
+    vec![
+        EqPreset::new("flat", [0.0; BAND_COUNT]),
+        EqPreset::new(
+            "bass-boost",
+            [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        ),
+        EqPreset::new(
+            "treble-boost",
+            [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0],
+        ),
+        EqPreset::new(
+            "vocal",
+            [-3.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.0, 1.0, 0.0, -1.0],
+        ),
+        EqPreset::new(
+            "rock",
+            [5.0, 3.0, 1.0, -1.0, -2.0, -1.0, 1.0, 3.0, 4.0, 5.0],
+        ),
+        EqPreset::new("pop", [-1.0, 1.0, 3.0, 4.0, 3.0, 0.0, -1.0, -1.0, 0.0, 1.0]),
+        EqPreset::new(
+            "classical",
+            [4.0, 3.0, 2.0, 1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0],
+        ),
+        EqPreset::new(
+            "electronic",
+            [5.0, 4.0, 1.0, 0.0, -2.0, 1.0, 0.0, 1.0, 4.0, 5.0],
+        ),
+    ]
+}
+
+/// Band gains shared between the UI and every [`Equalizer`] in the sink, so
+/// edits are heard straight away.
+#[derive(Debug)]
+pub struct EqControl {
+    gains: [AtomicU32; BAND_COUNT],
+    version: AtomicU32,
+}
+
+impl EqControl {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            gains: std::array::from_fn(|_| AtomicU32::new(0.0_f32.to_bits())),
+            version: AtomicU32::new(0),
+        })
+    }
+
+    pub fn set_gains(&self, gains: &EqGains) {
+        for (slot, gain) in self.gains.iter().zip(gains) {
+            slot.store(gain.to_bits(), Ordering::Relaxed);
+        }
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    fn gains(&self) -> EqGains {
+        std::array::from_fn(|band| f32::from_bits(self.gains[band].load(Ordering::Relaxed)))
+    }
+
+    fn version(&self) -> u32 {
+        self.version.load(Ordering::Acquire)
+    }
+}
+
+/// Ten peaking filters, one per band, run on every channel. Boosts are
+/// offset by lowering the overall level by the largest one, so the
+/// equalizer cannot push a track into clipping.
+pub struct Equalizer<S> {
+    input: S,
+    control: Arc<EqControl>,
+    version: Option<u32>,
+    /// `filters[channel][band]`, or empty while every band is flat.
+    filters: Vec<[Biquad; BAND_COUNT]>,
+    headroom: f64,
+    channel: usize,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+}
+
+impl<S: Source> Equalizer<S> {
+    pub fn new(input: S, control: Arc<EqControl>) -> Self {
+        let channels = input.channels();
+        let sample_rate = input.sample_rate();
+        Self {
+            input,
+            control,
+            version: None,
+            filters: Vec::new(),
+            headroom: 1.0,
+            channel: 0,
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Picks up new gains or a new stream format between frames.
+    fn refresh(&mut self) {
+        let version = self.control.version();
+        let channels = self.input.channels();
+        let sample_rate = self.input.sample_rate();
+        if self.version == Some(version)
+            && channels == self.channels
+            && sample_rate == self.sample_rate
+        {
+            return;
+        }
+        let format_changed = channels != self.channels || sample_rate != self.sample_rate;
+        self.version = Some(version);
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+
+        let gains = self.control.gains();
+        if gains.iter().all(|gain| *gain == 0.0) {
+            self.filters.clear();
+            self.headroom = 1.0;
+            return;
+        }
+
+        let rate = f64::from(sample_rate.max(1));
+        let tuned: [Biquad; BAND_COUNT] = std::array::from_fn(|band| {
+            // Bands at or above Nyquist are left out.
+            let frequency = f64::from(BAND_FREQUENCIES[band]).min(rate * 0.45);
+            let gain = if f64::from(BAND_FREQUENCIES[band]) < rate / 2.0 {
+                f64::from(gains[band])
+            } else {
+                0.0
+            };
+            Biquad::peaking(frequency, gain, BAND_Q, rate)
+        });
+        if format_changed || self.filters.len() != usize::from(channels) {
+            self.filters = vec![tuned; usize::from(channels)];
+        } else {
+            for filters in &mut self.filters {
+                for (filter, tuned) in filters.iter_mut().zip(&tuned) {
+                    filter.retune(tuned);
+                }
+            }
+        }
+        let boost = gains.iter().copied().fold(0.0_f32, f32::max);
+        self.headroom = 10f64.powf(-f64::from(boost) / 20.0);
+    }
+}
+
+impl<S: Source> Iterator for Equalizer<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.channel == 0 {
+            self.refresh();
+        }
+        let sample = self.input.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % usize::from(self.channels.max(1));
+
+        let Some(filters) = self.filters.get_mut(channel) else {
+            return Some(sample);
+        };
+        let mut value = f64::from(sample);
+        for filter in filters {
+            value = filter.process(value);
+        }
+        Some((value * self.headroom) as Sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Equalizer<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.channel = 0;
+        self.input.try_seek(pos)
+    }
+}
+
+/// What the EQ screen edits: the gains in use, which preset they came from
+/// and the selected band.
+#[derive(Debug, Clone)]
+pub struct EqEditor {
+    presets: Vec<EqPreset>,
+    pub name: String,
+    pub gains: EqGains,
+    pub selected_band: usize,
+}
+
+impl Default for EqEditor {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl EqEditor {
+    /// Starts flat. User presets replace built-in ones of the same name and
+    /// follow them in the cycle otherwise.
+    pub fn new(user_presets: Vec<EqPreset>) -> Self {
+        let mut presets = builtin_presets();
+        for preset in user_presets {
+            match presets.iter_mut().find(|known| known.name == preset.name) {
+                Some(known) => *known = preset,
+                None => presets.push(preset),
+            }
+        }
+        Self {
+            name: presets[0].name.clone(),
+            gains: presets[0].gains,
+            presets,
+            selected_band: 0,
+        }
+    }
+
+    pub fn cycle_preset(&mut self) {
+        let next = self
+            .presets
+            .iter()
+            .position(|preset| preset.name == self.name)
+            .map_or(0, |index| (index + 1) % self.presets.len());
+        self.name = self.presets[next].name.clone();
+        self.gains = self.presets[next].gains;
+    }
+
+    /// Restores saved gains, named after their preset when they still match
+    /// it and "custom" otherwise.
+    pub fn restore(&mut self, name: &str, gains: EqGains) {
+        self.gains = gains.map(|gain| gain.clamp(-MAX_GAIN_DB, MAX_GAIN_DB));
+        self.name = match self.presets.iter().find(|preset| preset.name == name) {
+            Some(preset) if preset.gains == self.gains => name.to_string(),
+            _ => "custom".to_string(),
+        };
+    }
+
+    pub fn select_previous_band(&mut self) {
+        self.selected_band = self.selected_band.saturating_sub(1);
+    }
+
+    pub fn select_next_band(&mut self) {
+        self.selected_band = (self.selected_band + 1).min(BAND_COUNT - 1);
+    }
+
+    pub fn raise(&mut self) {
+        self.adjust(GAIN_STEP_DB);
+    }
+
+    pub fn lower(&mut self) {
+        self.adjust(-GAIN_STEP_DB);
+    }
+
+    fn adjust(&mut self, step: f32) {
+        let gain = &mut self.gains[self.selected_band];
+        *gain = (*gain + step).clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+        self.name = "custom".to_string();
+    }
+}
+
+/// `"1k"` for 1000 Hz, `"62"` for 62 Hz.
+pub fn band_label(frequency: f32) -> String {
+    if frequency >= 1_000.0 {
+        format!("{}k", frequency / 1_000.0)
+    } else {
+        format!("{frequency}")
+    }
+}
+
+pub fn encode_eq(name: &str, gains: &EqGains) -> Vec<u8> {
+    let mut bytes = vec![1];
+    for gain in gains {
+        bytes.extend_from_slice(&gain.to_le_bytes());
+    }
+    bytes.extend_from_slice(name.as_bytes());
+    bytes
+}
+
+pub fn decode_eq(raw: &[u8]) -> Option<(String, EqGains)> {
+    let (&version, rest) = raw.split_first()?;
+    if version != 1 || rest.len() < BAND_COUNT * 4 {
+        return None;
+    }
+    let (gains_raw, name) = rest.split_at(BAND_COUNT * 4);
+    let mut gains = [0.0; BAND_COUNT];
+    for (gain, chunk) in gains.iter_mut().zip(gains_raw.chunks_exact(4)) {
+        *gain = f32::from_le_bytes(chunk.try_into().ok()?);
+    }
+    Some((String::from_utf8_lossy(name).to_string(), gains))
+}
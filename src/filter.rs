@@ -0,0 +1,61 @@
+/// Incremental filter over the file list. `matches` holds indices into
+/// `App::entries` in list order, so the selection keeps pointing at the same
+/// entry while the query changes.
+#[derive(Debug, Default)]
+pub struct FileFilter {
+    pub query: String,
+    pub editing: bool,
+    pub matches: Vec<usize>,
+}
+
+/// Matches `query` as a case-insensitive subsequence of `text`, ignoring
+/// whitespace in the query. Returns the char positions of the matched
+/// characters in `text`, preferring the earliest run of the first character
+/// that still lets the rest of the query match.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<Vec<usize>> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if query.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let text: Vec<char> = text
+        .chars()
+        .map(|ch| ch.to_lowercase().next().unwrap_or(ch))
+        .collect();
+    let mut positions = Vec::with_capacity(query.len());
+    let mut wanted = query.iter();
+    let mut next = wanted.next();
+    for (index, ch) in text.iter().enumerate() {
+        let Some(target) = next else {
+            break;
+        };
+        if ch == target {
+            positions.push(index);
+            next = wanted.next();
+        }
+    }
+    if next.is_some() {
+        return None;
+    }
+
+    tighten(&query, &text, &mut positions);
+    Some(positions)
+}
+
+/// The greedy scan matches as early as possible, which spreads the matched
+/// characters out. Walking back from the last match pulls every character as
+/// late as it can go, so "mix" highlights the word "mix" rather than an
+/// m, i and x scattered across the name.
+fn tighten(query: &[char], text: &[char], positions: &mut [usize]) {
+    let mut limit = text.len();
+    for (slot, target) in positions.iter_mut().zip(query).rev() {
+        if let Some(index) = text[*slot..limit].iter().rposition(|ch| ch == target) {
+            *slot += index;
+        }
+        limit = *slot;
+    }
+}
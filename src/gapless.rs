@@ -0,0 +1,167 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+use crate::metadata::GaplessInfo;
+
+/// Drops the encoder delay at the start of a track and the padding at its
+/// end, so consecutive tracks join without a click of silence.
+pub struct GaplessTrim<S> {
+    input: S,
+    delay: Duration,
+    /// Samples (not frames) left before the padding starts.
+    remaining: Option<u64>,
+    valid_frames: Option<u64>,
+}
+
+impl<S: Source> GaplessTrim<S> {
+    pub fn new(mut input: S, info: Option<GaplessInfo>) -> Self {
+        let Some(info) = info else {
+            return Self {
+                input,
+                delay: Duration::ZERO,
+                remaining: None,
+                valid_frames: None,
+            };
+        };
+
+        let channels = u64::from(input.channels().max(1));
+        for _ in 0..info.delay * channels {
+            if input.next().is_none() {
+                break;
+            }
+        }
+        let rate = f64::from(input.sample_rate().max(1));
+        Self {
+            delay: Duration::from_secs_f64(info.delay as f64 / rate),
+            remaining: info.valid_frames.map(|frames| frames * channels),
+            valid_frames: info.valid_frames,
+            input,
+        }
+    }
+}
+
+impl<S: Source> Iterator for GaplessTrim<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
+                return None;
+            }
+            *remaining -= 1;
+        }
+        self.input.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.input.size_hint();
+        match self.remaining {
+            Some(remaining) => {
+                let remaining = usize::try_from(remaining).unwrap_or(usize::MAX);
+                (
+                    lower.min(remaining),
+                    Some(upper.map_or(remaining, |upper| upper.min(remaining))),
+                )
+            }
+            None => (lower, upper),
+        }
+    }
+}
+
+impl<S: Source> Source for GaplessTrim<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        let span = self.input.current_span_len();
+        match self.remaining {
+            Some(remaining) => {
+                let remaining = usize::try_from(remaining).unwrap_or(usize::MAX);
+                Some(span.map_or(remaining, |span| span.min(remaining)))
+            }
+            None => span,
+        }
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self.valid_frames {
+            Some(frames) => Some(Duration::from_secs_f64(
+                frames as f64 / f64::from(self.input.sample_rate().max(1)),
+            )),
+            None => self
+                .input
+                .total_duration()
+                .map(|total| total.saturating_sub(self.delay)),
+        }
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos + self.delay)?;
+        if let Some(frames) = self.valid_frames {
+            let rate = f64::from(self.input.sample_rate().max(1));
+            let done = (pos.as_secs_f64() * rate) as u64;
+            let channels = u64::from(self.input.channels().max(1));
+            self.remaining = Some(frames.saturating_sub(done) * channels);
+        }
+        Ok(())
+    }
+}
+
+/// Ends its source early once `cancel` is set. Used for the preloaded next
+/// track, which cannot be taken back out of the sink when the queue changes.
+pub struct Cancellable<S> {
+    input: S,
+    cancel: Arc<AtomicBool>,
+}
+
+impl<S: Source> Cancellable<S> {
+    pub fn new(input: S, cancel: Arc<AtomicBool>) -> Self {
+        Self { input, cancel }
+    }
+}
+
+impl<S: Source> Iterator for Cancellable<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.input.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.input.size_hint().1)
+    }
+}
+
+impl<S: Source> Source for Cancellable<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
@@ -0,0 +1,721 @@
+use std::fmt;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Everything a key can be bound to. Names are the kebab-case strings used in
+/// the `[keys]` table of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Up,
+    Down,
+    Filter,
+    Activate,
+    PlayFromSelected,
+    Enqueue,
+    EnqueueNext,
+    Unqueue,
+    ClearQueue,
+    Next,
+    Previous,
+    SeekBack,
+    SeekForward,
+    SeekBackLong,
+    SeekForwardLong,
+    Jump(u8),
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Shuffle,
+    Repeat,
+    SaveQueue,
+    SaveFolder,
+    ToggleLibraryRoot,
+    RescanLibrary,
+    SplitView,
+    PlayerView,
+    LibraryView,
+    LibraryBack,
+    LibraryCategory,
+    CycleTheme,
+    EqView,
+    EqPreset,
+    SpeedUp,
+    SpeedDown,
+    TogglePitch,
+    AddBookmark,
+    Bookmarks,
+    NextChapter,
+    PreviousChapter,
+    Help,
+}
+
+impl Action {
+    const SIMPLE: [(Action, &'static str); 41] = [
+        (Action::Quit, "quit"),
+        (Action::Up, "up"),
+        (Action::Down, "down"),
+        (Action::Filter, "filter"),
+        (Action::Activate, "activate"),
+        (Action::PlayFromSelected, "play-from-selected"),
+        (Action::Enqueue, "enqueue"),
+        (Action::EnqueueNext, "enqueue-next"),
+        (Action::Unqueue, "unqueue"),
+        (Action::ClearQueue, "clear-queue"),
+        (Action::Next, "next"),
+        (Action::Previous, "previous"),
+        (Action::SeekBack, "seek-back"),
+        (Action::SeekForward, "seek-forward"),
+        (Action::SeekBackLong, "seek-back-long"),
+        (Action::SeekForwardLong, "seek-forward-long"),
+        (Action::VolumeUp, "volume-up"),
+        (Action::VolumeDown, "volume-down"),
+        (Action::Mute, "mute"),
+        (Action::Shuffle, "shuffle"),
+        (Action::Repeat, "repeat"),
+        (Action::SaveQueue, "save-queue"),
+        (Action::SaveFolder, "save-folder"),
+        (Action::ToggleLibraryRoot, "toggle-library-root"),
+        (Action::RescanLibrary, "rescan-library"),
+        (Action::SplitView, "split-view"),
+        (Action::PlayerView, "player-view"),
+        (Action::LibraryView, "library-view"),
+        (Action::LibraryBack, "library-back"),
+        (Action::LibraryCategory, "library-category"),
+        (Action::CycleTheme, "cycle-theme"),
+        (Action::EqView, "eq-view"),
+        (Action::EqPreset, "eq-preset"),
+        (Action::SpeedUp, "speed-up"),
+        (Action::SpeedDown, "speed-down"),
+        (Action::TogglePitch, "toggle-pitch"),
+        (Action::AddBookmark, "add-bookmark"),
+        (Action::Bookmarks, "bookmarks"),
+        (Action::NextChapter, "next-chapter"),
+        (Action::PreviousChapter, "previous-chapter"),
+        (Action::Help, "help"),
+    ];
+
+    /// Parses an action name; `jump-0` to `jump-9` seek to tenths of the track.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(digit) = name.strip_prefix("jump-") {
+            return match digit.parse::<u8>() {
+                Ok(tenth) if tenth <= 9 && digit.len() == 1 => Some(Action::Jump(tenth)),
+                _ => None,
+            };
+        }
+        Self::SIMPLE
+            .iter()
+            .find(|(_, simple)| *simple == name)
+            .map(|(action, _)| *action)
+    }
+}
+
+/// One key press, with the modifiers that matter for matching. Shift is folded
+/// into the character for printable keys, so `W` and `shift+w` are the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(ch) if modifiers.contains(KeyModifiers::SHIFT) => Self {
+                code: KeyCode::Char(ch.to_uppercase().next().unwrap_or(ch)),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => Self {
+                code: KeyCode::BackTab,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::BackTab => Self {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Self { code, modifiers },
+        }
+    }
+
+    /// Parses `x`, `W`, `ctrl+x`, `alt+shift+left`, `space`, `+` or `ctrl++`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let (modifier_part, key_part) = if text == "+" {
+            ("", "+")
+        } else if let Some(rest) = text.strip_suffix("++") {
+            (rest, "+")
+        } else {
+            match text.rsplit_once('+') {
+                Some((modifiers, key)) => (modifiers, key),
+                None => ("", text),
+            }
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in modifier_part.split('+').filter(|part| !part.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier `{modifier}` in `{text}`")),
+            };
+        }
+
+        let mut chars = key_part.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(ch), None) => KeyCode::Char(ch),
+            _ => match key_part.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(number) if (1..=12).contains(&number) => KeyCode::F(number),
+                    _ => return Err(format!("unknown key `{key_part}` in `{text}`")),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl From<KeyEvent> for KeyPress {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(ch) => write!(f, "{ch}"),
+            KeyCode::F(number) => write!(f, "F{number}"),
+            KeyCode::BackTab => write!(f, "Shift+Tab"),
+            code => write!(f, "{code}"),
+        }
+    }
+}
+
+type KeySequence = Vec<KeyPress>;
+
+fn parse_sequence(text: &str) -> Result<KeySequence, String> {
+    let sequence = text
+        .split_whitespace()
+        .map(KeyPress::parse)
+        .collect::<Result<KeySequence, _>>()?;
+    if sequence.is_empty() {
+        return Err("empty key binding".to_string());
+    }
+    Ok(sequence)
+}
+
+fn display_sequence(sequence: &[KeyPress]) -> String {
+    sequence
+        .iter()
+        .map(KeyPress::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What feeding a key press into the keymap produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyOutcome {
+    Action(Action),
+    /// The key started or continued a multi-key sequence.
+    Pending,
+    Unbound,
+}
+
+/// Bindings from key sequences to actions, plus the keys typed so far of a
+/// sequence that is not complete yet.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeySequence, Action)>,
+    pending: KeySequence,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = default_bindings()
+            .into_iter()
+            .flat_map(|(action, keys)| {
+                keys.into_iter().map(move |key| {
+                    (
+                        parse_sequence(key).expect("default key bindings are valid"),
+                        action,
+                    )
+                })
+            })
+            .collect();
+        Self {
+            bindings,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Builds the keymap from the `[keys]` config table. Every action listed
+    /// replaces its default keys; an empty list unbinds it. Default keys that
+    /// the user reassigned elsewhere are dropped, while clashes between two
+    /// user bindings are reported.
+    pub fn from_config(table: &toml::Table, problems: &mut Vec<String>) -> Self {
+        // In table order, so problems are reported in the same order each run.
+        let mut user: Vec<(Action, Vec<KeySequence>)> = Vec::new();
+        for (name, value) in table {
+            let Some(action) = Action::from_name(name) else {
+                problems.push(format!("keys.{name}: unknown action"));
+                continue;
+            };
+            let texts: Vec<&str> = match value {
+                toml::Value::String(text) => vec![text.as_str()],
+                toml::Value::Array(items) => {
+                    let texts: Vec<&str> = items.iter().filter_map(toml::Value::as_str).collect();
+                    if texts.len() != items.len() {
+                        problems.push(format!("keys.{name}: expected a list of strings"));
+                        continue;
+                    }
+                    texts
+                }
+                _ => {
+                    problems.push(format!(
+                        "keys.{name}: expected a string or a list of strings"
+                    ));
+                    continue;
+                }
+            };
+
+            let mut sequences = Vec::new();
+            for text in texts {
+                match parse_sequence(text) {
+                    Ok(sequence) => sequences.push(sequence),
+                    Err(err) => problems.push(format!("keys.{name}: {err}")),
+                }
+            }
+            user.push((action, sequences));
+        }
+
+        let mut bindings: Vec<(KeySequence, Action)> = Vec::new();
+        for (action, sequences) in &user {
+            for sequence in sequences {
+                if bindings.contains(&(sequence.clone(), *action)) {
+                    continue;
+                }
+                if let Some((_, other)) = bindings
+                    .iter()
+                    .find(|(bound, _)| bound.starts_with(sequence) || sequence.starts_with(bound))
+                {
+                    problems.push(format!(
+                        "keys: `{}` is bound to both {} and {}",
+                        display_sequence(sequence),
+                        action_name(*action),
+                        action_name(*other),
+                    ));
+                    continue;
+                }
+                bindings.push((sequence.clone(), *action));
+            }
+        }
+
+        for (sequence, action) in Self::default().bindings {
+            if user.iter().any(|(overridden, _)| *overridden == action) {
+                continue;
+            }
+            let clashes = bindings
+                .iter()
+                .any(|(bound, _)| bound.starts_with(&sequence) || sequence.starts_with(bound));
+            if !clashes {
+                bindings.push((sequence, action));
+            }
+        }
+
+        Self {
+            bindings,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feeds one key press. Keys that cannot continue the pending sequence
+    /// abandon it and are then tried on their own.
+    pub fn handle(&mut self, key: KeyEvent) -> KeyOutcome {
+        let press = KeyPress::from(key);
+        self.pending.push(press);
+        if let Some(outcome) = self.match_pending() {
+            return outcome;
+        }
+
+        let started_sequence = self.pending.len() > 1;
+        self.pending.clear();
+        if started_sequence {
+            self.pending.push(press);
+            if let Some(outcome) = self.match_pending() {
+                return outcome;
+            }
+            self.pending.clear();
+        }
+        KeyOutcome::Unbound
+    }
+
+    fn match_pending(&mut self) -> Option<KeyOutcome> {
+        if let Some((_, action)) = self
+            .bindings
+            .iter()
+            .find(|(sequence, _)| *sequence == self.pending)
+        {
+            self.pending.clear();
+            return Some(KeyOutcome::Action(*action));
+        }
+        self.bindings
+            .iter()
+            .any(|(sequence, _)| sequence.starts_with(&self.pending))
+            .then_some(KeyOutcome::Pending)
+    }
+
+    pub fn cancel_pending(&mut self) -> bool {
+        let had_pending = !self.pending.is_empty();
+        self.pending.clear();
+        had_pending
+    }
+
+    pub fn pending_text(&self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| display_sequence(&self.pending))
+    }
+
+    pub fn keys_for(&self, action: Action) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| *bound == action)
+            .map(|(sequence, _)| display_sequence(sequence))
+    }
+
+    /// Every binding as `keys: label`, built from the active keymap for the
+    /// help overlay. Actions without a key are left out; groups such as
+    /// Up/Down share one label.
+    pub fn help_entries(&self) -> Vec<String> {
+        let mut entries: Vec<String> = HELP_GROUPS
+            .iter()
+            .filter_map(|(label, actions)| self.help_entry(label, actions))
+            .collect();
+
+        let first_jump = self.keys_for(Action::Jump(0));
+        let last_jump = self.keys_for(Action::Jump(9));
+        if let (Some(first), Some(last)) = (first_jump, last_jump) {
+            entries.push(format!("{first}..{last}: Jump"));
+        }
+        entries
+    }
+
+    /// The few bindings shown in the footer, ending with the key for the
+    /// full list.
+    pub fn short_help(&self) -> String {
+        HELP_GROUPS
+            .iter()
+            .filter(|(label, _)| FOOTER_HELP.contains(label))
+            .filter_map(|(label, actions)| self.help_entry(label, actions))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    fn help_entry(&self, label: &str, actions: &[Action]) -> Option<String> {
+        let keys: Vec<String> = actions
+            .iter()
+            .filter_map(|action| self.keys_for(*action))
+            .collect();
+        (!keys.is_empty()).then(|| format!("{}: {label}", keys.join("/")))
+    }
+}
+
+fn action_name(action: Action) -> String {
+    match action {
+        Action::Jump(tenth) => format!("jump-{tenth}"),
+        action => Action::SIMPLE
+            .iter()
+            .find(|(simple, _)| *simple == action)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+const HELP_GROUPS: [(&str, &[Action]); 32] = [
+    ("Navigate", &[Action::Up, Action::Down]),
+    ("Filter", &[Action::Filter]),
+    ("Open/Play/Pause", &[Action::Activate]),
+    ("Play Folder", &[Action::PlayFromSelected]),
+    ("Queue/Play Next", &[Action::Enqueue, Action::EnqueueNext]),
+    ("Unqueue", &[Action::Unqueue]),
+    ("Clear Queue", &[Action::ClearQueue]),
+    ("Next/Prev", &[Action::Next, Action::Previous]),
+    (
+        "Next/Prev Chapter",
+        &[Action::NextChapter, Action::PreviousChapter],
+    ),
+    ("±5s", &[Action::SeekBack, Action::SeekForward]),
+    ("±30s", &[Action::SeekBackLong, Action::SeekForwardLong]),
+    ("Volume", &[Action::VolumeUp, Action::VolumeDown]),
+    ("Mute", &[Action::Mute]),
+    ("Speed", &[Action::SpeedUp, Action::SpeedDown]),
+    ("Keep Pitch", &[Action::TogglePitch]),
+    ("Bookmark", &[Action::AddBookmark]),
+    ("Bookmarks", &[Action::Bookmarks]),
+    ("Shuffle", &[Action::Shuffle]),
+    ("Repeat", &[Action::Repeat]),
+    (
+        "Save Queue/Folder",
+        &[Action::SaveQueue, Action::SaveFolder],
+    ),
+    ("Library Root", &[Action::ToggleLibraryRoot]),
+    ("Rescan", &[Action::RescanLibrary]),
+    ("Split", &[Action::SplitView]),
+    ("Player", &[Action::PlayerView]),
+    ("Library", &[Action::LibraryView]),
+    ("Category", &[Action::LibraryCategory]),
+    ("Up a Level", &[Action::LibraryBack]),
+    ("EQ", &[Action::EqView]),
+    ("EQ Preset", &[Action::EqPreset]),
+    ("Theme", &[Action::CycleTheme]),
+    ("Help", &[Action::Help]),
+    ("Quit", &[Action::Quit]),
+];
+
+/// Labels from `HELP_GROUPS` that make it into the footer.
+const FOOTER_HELP: [&str; 4] = ["Open/Play/Pause", "Next/Prev", "Help", "Quit"];
+
+fn default_bindings() -> Vec<(Action, Vec<&'static str>)> {
+    let mut bindings = vec![
+        (Action::Quit, vec!["q", "ctrl+c"]),
+        (Action::Up, vec!["up"]),
+        (Action::Down, vec!["down"]),
+        (Action::Filter, vec!["/"]),
+        (Action::Activate, vec!["enter"]),
+        (Action::PlayFromSelected, vec!["f"]),
+        (Action::Enqueue, vec!["a"]),
+        (Action::EnqueueNext, vec!["i"]),
+        (Action::Unqueue, vec!["d"]),
+        (Action::ClearQueue, vec!["c"]),
+        (Action::Next, vec!["n"]),
+        (Action::Previous, vec!["p"]),
+        (Action::SeekBack, vec!["left"]),
+        (Action::SeekForward, vec!["right"]),
+        (Action::SeekBackLong, vec!["["]),
+        (Action::SeekForwardLong, vec!["]"]),
+        (Action::VolumeUp, vec!["+", "="]),
+        (Action::VolumeDown, vec!["-"]),
+        (Action::Mute, vec!["m"]),
+        (Action::Shuffle, vec!["s"]),
+        (Action::Repeat, vec!["r"]),
+        (Action::SaveQueue, vec!["w"]),
+        (Action::SaveFolder, vec!["W"]),
+        (Action::ToggleLibraryRoot, vec!["L"]),
+        (Action::RescanLibrary, vec!["U"]),
+        (Action::SplitView, vec!["1"]),
+        (Action::PlayerView, vec!["2"]),
+        (Action::LibraryView, vec!["3"]),
+        (Action::LibraryBack, vec!["backspace"]),
+        (Action::LibraryCategory, vec!["tab"]),
+        (Action::CycleTheme, vec!["t"]),
+        (Action::EqView, vec!["4"]),
+        (Action::EqPreset, vec!["e"]),
+        (Action::SpeedUp, vec![">"]),
+        (Action::SpeedDown, vec!["<"]),
+        (Action::TogglePitch, vec!["P"]),
+        (Action::AddBookmark, vec!["b"]),
+        (Action::Bookmarks, vec!["B"]),
+        (Action::NextChapter, vec!["."]),
+        (Action::PreviousChapter, vec![","]),
+        (Action::Help, vec!["?"]),
+    ];
+    const JUMP_KEYS: [&str; 10] = [
+        "alt+0", "alt+1", "alt+2", "alt+3", "alt+4", "alt+5", "alt+6", "alt+7", "alt+8", "alt+9",
+    ];
+    for (tenth, key) in JUMP_KEYS.into_iter().enumerate() {
+        bindings.push((Action::Jump(tenth as u8), vec![key]));
+    }
+    bindings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyPress {
+        KeyPress::new(code, modifiers)
+    }
+
+    fn keymap(config: &str) -> (Keymap, Vec<String>) {
+        let table: toml::Table = config.parse().unwrap();
+        let mut problems = Vec::new();
+        let keymap = Keymap::from_config(&table, &mut problems);
+        (keymap, problems)
+    }
+
+    fn key(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn parses_single_keys() {
+        let char = |ch| press(KeyCode::Char(ch), KeyModifiers::NONE);
+        assert_eq!(KeyPress::parse("x"), Ok(char('x')));
+        assert_eq!(KeyPress::parse("W"), Ok(char('W')));
+        assert_eq!(KeyPress::parse("shift+w"), Ok(char('W')));
+        assert_eq!(KeyPress::parse("+"), Ok(char('+')));
+        assert_eq!(KeyPress::parse("space"), Ok(char(' ')));
+        assert_eq!(
+            KeyPress::parse("ctrl+x"),
+            Ok(press(KeyCode::Char('x'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            KeyPress::parse("ctrl++"),
+            Ok(press(KeyCode::Char('+'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            KeyPress::parse("Alt+Shift+Left"),
+            Ok(press(
+                KeyCode::Left,
+                KeyModifiers::ALT | KeyModifiers::SHIFT
+            ))
+        );
+        assert_eq!(
+            KeyPress::parse("shift+tab"),
+            Ok(press(KeyCode::BackTab, KeyModifiers::NONE))
+        );
+        assert_eq!(
+            KeyPress::parse("f12"),
+            Ok(press(KeyCode::F(12), KeyModifiers::NONE))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_modifiers() {
+        assert!(KeyPress::parse("hyper+x").is_err());
+        assert!(KeyPress::parse("f13").is_err());
+        assert!(KeyPress::parse("f0").is_err());
+        assert!(KeyPress::parse("nope").is_err());
+        assert!(parse_sequence("").is_err());
+        assert!(parse_sequence("   ").is_err());
+        assert!(parse_sequence("g nope").is_err());
+    }
+
+    #[test]
+    fn parses_sequences_on_whitespace() {
+        let sequence = parse_sequence("g  ctrl+g").unwrap();
+        assert_eq!(
+            sequence,
+            vec![
+                press(KeyCode::Char('g'), KeyModifiers::NONE),
+                press(KeyCode::Char('g'), KeyModifiers::CONTROL),
+            ]
+        );
+        assert_eq!(display_sequence(&sequence), "g Ctrl+g");
+    }
+
+    #[test]
+    fn parses_action_names() {
+        assert_eq!(Action::from_name("quit"), Some(Action::Quit));
+        assert_eq!(Action::from_name("jump-7"), Some(Action::Jump(7)));
+        assert_eq!(Action::from_name("jump-10"), None);
+        assert_eq!(Action::from_name("jump-07"), None);
+        assert_eq!(Action::from_name("bogus"), None);
+    }
+
+    #[test]
+    fn user_bindings_replace_defaults() {
+        let (mut keymap, problems) = keymap("quit = \"ctrl+q\"\nmute = []\nup = \"n\"");
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(keymap.keys_for(Action::Quit).as_deref(), Some("Ctrl+q"));
+        assert_eq!(keymap.handle(key('q')), KeyOutcome::Unbound);
+        assert_eq!(keymap.handle(key('m')), KeyOutcome::Unbound);
+        // `n` went to `up`, so the default binding for `next` is dropped.
+        assert_eq!(keymap.handle(key('n')), KeyOutcome::Action(Action::Up));
+        assert_eq!(keymap.keys_for(Action::Next), None);
+        assert_eq!(
+            keymap.handle(key('p')),
+            KeyOutcome::Action(Action::Previous)
+        );
+    }
+
+    #[test]
+    fn reports_problems_in_a_stable_order() {
+        let config = "up = \"x\"\nbogus = \"y\"\nnext = \"x\"\nhelp = \"g\"\nprevious = [\"x\", \"hyper+z\"]\nquit = \"g g\"\nmute = 3";
+        let expected = [
+            "keys.bogus: unknown action",
+            "keys.mute: expected a string or a list of strings",
+            "keys.previous: unknown modifier `hyper` in `hyper+z`",
+            "keys: `x` is bound to both previous and next",
+            "keys: `g g` is bound to both quit and help",
+            "keys: `x` is bound to both up and next",
+        ];
+        for _ in 0..5 {
+            let (_, problems) = keymap(config);
+            assert_eq!(problems, expected);
+        }
+    }
+
+    #[test]
+    fn matches_multi_key_sequences() {
+        let (mut keymap, problems) = keymap("help = \"g h\"\nquit = [\"g q\", \"q\"]");
+        assert!(problems.is_empty(), "{problems:?}");
+
+        assert_eq!(keymap.handle(key('g')), KeyOutcome::Pending);
+        assert_eq!(keymap.pending_text().as_deref(), Some("g"));
+        assert_eq!(keymap.handle(key('h')), KeyOutcome::Action(Action::Help));
+        assert_eq!(keymap.pending_text(), None);
+
+        assert_eq!(keymap.handle(key('g')), KeyOutcome::Pending);
+        assert_eq!(keymap.handle(key('q')), KeyOutcome::Action(Action::Quit));
+        assert_eq!(keymap.handle(key('q')), KeyOutcome::Action(Action::Quit));
+    }
+
+    #[test]
+    fn abandoned_sequence_retries_the_key_alone() {
+        let (mut keymap, _) = keymap("help = \"g h\"");
+
+        assert_eq!(keymap.handle(key('g')), KeyOutcome::Pending);
+        assert_eq!(keymap.handle(key('n')), KeyOutcome::Action(Action::Next));
+        assert_eq!(keymap.pending_text(), None);
+
+        assert_eq!(keymap.handle(key('g')), KeyOutcome::Pending);
+        assert_eq!(keymap.handle(key('g')), KeyOutcome::Pending);
+        assert_eq!(keymap.handle(key('h')), KeyOutcome::Action(Action::Help));
+
+        assert_eq!(keymap.handle(key('g')), KeyOutcome::Pending);
+        assert_eq!(keymap.handle(key('x')), KeyOutcome::Unbound);
+        assert_eq!(keymap.pending_text(), None);
+
+        assert_eq!(keymap.handle(key('g')), KeyOutcome::Pending);
+        assert!(keymap.cancel_pending());
+        assert!(!keymap.cancel_pending());
+    }
+
+    #[test]
+    fn shifted_events_match_uppercase_bindings() {
+        let mut keymap = Keymap::default();
+        let event = KeyEvent::new(KeyCode::Char('w'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.handle(event), KeyOutcome::Action(Action::SaveFolder));
+        let event = KeyEvent::new(KeyCode::Char('W'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.handle(event), KeyOutcome::Action(Action::SaveFolder));
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::metadata::{
+    TrackMetadata, decode_metadata, encode_metadata, is_audio_file, read_track_info,
+};
+use crate::player::probe_duration;
+
+const PROGRESS_INTERVAL: usize = 25;
+
+#[derive(Debug, Clone)]
+pub struct LibraryTrack {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: u64,
+    pub duration: Option<Duration>,
+    pub metadata: TrackMetadata,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ScanEvent {
+    Progress {
+        scanned: usize,
+        total: usize,
+    },
+    Finished {
+        total: usize,
+        updated: usize,
+        removed: usize,
+    },
+}
+
+/// Index of every audio file below the configured library roots. The scan
+/// thread writes straight into the `library` tree; the UI thread only follows
+/// its progress and reads the tree when a view needs the whole collection.
+#[derive(Debug)]
+pub struct Library {
+    tracks: sled::Tree,
+    roots: sled::Tree,
+    scan_rx: Option<Receiver<ScanEvent>>,
+    last_event: Option<ScanEvent>,
+}
+
+impl Library {
+    pub fn open(db: &sled::Db) -> Option<Self> {
+        Some(Self {
+            tracks: db.open_tree("library").ok()?,
+            roots: db.open_tree("library_roots").ok()?,
+            scan_rx: None,
+            last_event: None,
+        })
+    }
+
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots
+            .iter()
+            .keys()
+            .flatten()
+            .map(|key| PathBuf::from(String::from_utf8_lossy(&key).to_string()))
+            .collect()
+    }
+
+    pub fn has_root(&self, root: &Path) -> bool {
+        let key = root.to_string_lossy().to_string();
+        matches!(self.roots.contains_key(key.as_bytes()), Ok(true))
+    }
+
+    pub fn add_root(&self, root: &Path) {
+        let key = root.to_string_lossy().to_string();
+        let _ = self.roots.insert(key.as_bytes(), &[]);
+    }
+
+    pub fn remove_root(&self, root: &Path) {
+        let key = root.to_string_lossy().to_string();
+        let _ = self.roots.remove(key.as_bytes());
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        self.scan_rx.is_some()
+    }
+
+    /// Starts a background rescan. Files whose size and modification time are
+    /// unchanged are skipped, and files no longer below any root are dropped.
+    pub fn rescan(&mut self) -> bool {
+        if self.is_scanning() {
+            return false;
+        }
+
+        let roots = self.roots();
+        let tracks = self.tracks.clone();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || scan(&roots, &tracks, &tx));
+        self.scan_rx = Some(rx);
+        true
+    }
+
+    /// Drains scan events. Returns true once a scan has finished, so views
+    /// built from `tracks()` know to refresh.
+    pub fn update(&mut self) -> bool {
+        let mut disconnect = false;
+
+        if let Some(rx) = &self.scan_rx {
+            loop {
+                match rx.try_recv() {
+                    Ok(event) => self.last_event = Some(event),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        disconnect = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if disconnect {
+            self.scan_rx = None;
+        }
+        disconnect
+    }
+
+    pub fn progress_text(&self) -> Option<String> {
+        match self.last_event? {
+            ScanEvent::Progress { scanned, total } => {
+                Some(format!("Library: scanning {scanned}/{total}"))
+            }
+            ScanEvent::Finished {
+                total,
+                updated,
+                removed,
+            } => Some(format!(
+                "Library: {total} tracks ({updated} updated, {removed} removed)"
+            )),
+        }
+    }
+
+    pub fn tracks(&self) -> Vec<LibraryTrack> {
+        self.tracks
+            .iter()
+            .flatten()
+            .filter_map(|(key, raw)| {
+                let path = PathBuf::from(String::from_utf8_lossy(&key).to_string());
+                decode_track(path, &raw)
+            })
+            .collect()
+    }
+
+    /// Paths of every indexed track, without decoding their details.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.tracks
+            .iter()
+            .keys()
+            .flatten()
+            .map(|key| PathBuf::from(String::from_utf8_lossy(&key).to_string()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LibraryCategory {
+    #[default]
+    Artist,
+    Genre,
+    Year,
+}
+
+impl LibraryCategory {
+    pub fn label(self) -> &'static str {
+        match self {
+            LibraryCategory::Artist => "Artists",
+            LibraryCategory::Genre => "Genres",
+            LibraryCategory::Year => "Years",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            LibraryCategory::Artist => LibraryCategory::Genre,
+            LibraryCategory::Genre => LibraryCategory::Year,
+            LibraryCategory::Year => LibraryCategory::Artist,
+        }
+    }
+
+    fn group_of(self, track: &LibraryTrack) -> String {
+        let metadata = &track.metadata;
+        match self {
+            LibraryCategory::Artist => metadata
+                .artist
+                .clone()
+                .unwrap_or_else(|| "Unknown Artist".to_string()),
+            LibraryCategory::Genre => metadata
+                .genre
+                .clone()
+                .unwrap_or_else(|| "Unknown Genre".to_string()),
+            LibraryCategory::Year => metadata
+                .year
+                .map(|year| year.to_string())
+                .unwrap_or_else(|| "Unknown Year".to_string()),
+        }
+    }
+}
+
+fn album_of(track: &LibraryTrack) -> String {
+    track
+        .metadata
+        .album
+        .clone()
+        .unwrap_or_else(|| "Unknown Album".to_string())
+}
+
+#[derive(Debug, Clone)]
+pub enum LibraryItem {
+    Up,
+    Group(String),
+    Track(Box<LibraryTrack>),
+}
+
+impl LibraryItem {
+    pub fn label(&self) -> String {
+        match self {
+            LibraryItem::Up => "..".to_string(),
+            LibraryItem::Group(name) => name.clone(),
+            LibraryItem::Track(track) => {
+                let title = track.metadata.title.clone().unwrap_or_else(|| {
+                    track
+                        .path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                });
+                match track.metadata.track_number {
+                    Some(number) => format!("{number:02}. {title}"),
+                    None => title,
+                }
+            }
+        }
+    }
+}
+
+/// Drill-down view over the library index: category group, then album, then
+/// the album's tracks in track-number order.
+#[derive(Debug, Default)]
+pub struct LibraryBrowser {
+    pub category: LibraryCategory,
+    pub trail: Vec<String>,
+    pub items: Vec<LibraryItem>,
+    pub selected_index: usize,
+    tracks: Vec<LibraryTrack>,
+}
+
+impl LibraryBrowser {
+    pub fn set_tracks(&mut self, tracks: Vec<LibraryTrack>) {
+        self.tracks = tracks;
+        self.rebuild();
+    }
+
+    pub fn cycle_category(&mut self) {
+        self.category = self.category.next();
+        self.trail.clear();
+        self.selected_index = 0;
+        self.rebuild();
+    }
+
+    pub fn move_up(&mut self) {
+        self.selected_index = self.selected_index.saturating_sub(1);
+    }
+
+    pub fn move_down(&mut self) {
+        if !self.items.is_empty() {
+            self.selected_index = (self.selected_index + 1).min(self.items.len() - 1);
+        }
+    }
+
+    /// Opens the selected group, or returns the listed tracks and the index of
+    /// the selected one when a track is chosen.
+    pub fn activate(&mut self) -> Option<(Vec<LibraryTrack>, usize)> {
+        match self.items.get(self.selected_index)?.clone() {
+            LibraryItem::Up => {
+                self.go_up();
+                None
+            }
+            LibraryItem::Group(name) => {
+                self.trail.push(name);
+                self.selected_index = 0;
+                self.rebuild();
+                None
+            }
+            LibraryItem::Track(_) => {
+                let start = self.items[..self.selected_index]
+                    .iter()
+                    .filter(|item| matches!(item, LibraryItem::Track(_)))
+                    .count();
+                let tracks = self
+                    .items
+                    .iter()
+                    .filter_map(|item| match item {
+                        LibraryItem::Track(track) => Some(track.as_ref().clone()),
+                        _ => None,
+                    })
+                    .collect();
+                Some((tracks, start))
+            }
+        }
+    }
+
+    pub fn go_up(&mut self) {
+        let Some(left) = self.trail.pop() else {
+            return;
+        };
+        self.rebuild();
+        self.selected_index = self
+            .items
+            .iter()
+            .position(|item| matches!(item, LibraryItem::Group(name) if *name == left))
+            .unwrap_or(0);
+    }
+
+    fn rebuild(&mut self) {
+        let category = self.category;
+        let in_group = |track: &&LibraryTrack| {
+            self.trail
+                .first()
+                .is_none_or(|group| category.group_of(track) == *group)
+        };
+
+        self.items = match self.trail.as_slice() {
+            [] => group_names(self.tracks.iter().map(|track| category.group_of(track))),
+            [_] => group_names(self.tracks.iter().filter(in_group).map(album_of)),
+            [_, album, ..] => {
+                let mut tracks: Vec<&LibraryTrack> = self
+                    .tracks
+                    .iter()
+                    .filter(in_group)
+                    .filter(|track| album_of(track) == *album)
+                    .collect();
+                tracks.sort_by(|a, b| {
+                    a.metadata
+                        .track_number
+                        .unwrap_or(u32::MAX)
+                        .cmp(&b.metadata.track_number.unwrap_or(u32::MAX))
+                        .then_with(|| a.path.cmp(&b.path))
+                });
+                tracks
+                    .into_iter()
+                    .map(|track| LibraryItem::Track(Box::new(track.clone())))
+                    .collect()
+            }
+        };
+
+        if !self.trail.is_empty() {
+            self.items.insert(0, LibraryItem::Up);
+        }
+        if self.selected_index >= self.items.len() {
+            self.selected_index = self.items.len().saturating_sub(1);
+        }
+    }
+}
+
+fn group_names(names: impl Iterator<Item = String>) -> Vec<LibraryItem> {
+    let mut names: Vec<String> = names.collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names.dedup();
+    names.into_iter().map(LibraryItem::Group).collect()
+}
+
+fn scan(roots: &[PathBuf], tracks: &sled::Tree, tx: &Sender<ScanEvent>) {
+    let mut files = Vec::new();
+    for root in roots {
+        collect_audio_files(root, &mut files);
+    }
+    files.sort();
+    files.dedup();
+
+    let total = files.len();
+    let mut updated = 0;
+    let mut seen = HashSet::with_capacity(total);
+
+    for (index, path) in files.into_iter().enumerate() {
+        if index % PROGRESS_INTERVAL == 0
+            && tx
+                .send(ScanEvent::Progress {
+                    scanned: index,
+                    total,
+                })
+                .is_err()
+        {
+            return;
+        }
+
+        let key = path.to_string_lossy().to_string();
+        seen.insert(key.clone());
+        let Ok(file_metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        let size = file_metadata.len();
+        let modified = file_metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+
+        let unchanged = matches!(
+            tracks.get(key.as_bytes()),
+            Ok(Some(raw)) if decode_track(path.clone(), &raw)
+                .is_some_and(|known| known.size == size && known.modified == modified)
+        );
+        if unchanged {
+            continue;
+        }
+
+        let (metadata, duration) = read_track_info(&path).unwrap_or_default();
+        let duration = duration.or_else(|| probe_duration(&path));
+        let track = LibraryTrack {
+            path,
+            size,
+            modified,
+            duration,
+            metadata,
+        };
+        let _ = tracks.insert(key.as_bytes(), encode_track(&track));
+        updated += 1;
+    }
+
+    let stale: Vec<_> = tracks
+        .iter()
+        .keys()
+        .flatten()
+        .filter(|key| !seen.contains(String::from_utf8_lossy(key).as_ref()))
+        .collect();
+    let removed = stale.len();
+    for key in stale {
+        let _ = tracks.remove(key);
+    }
+    let _ = tracks.flush();
+
+    let _ = tx.send(ScanEvent::Finished {
+        total,
+        updated,
+        removed,
+    });
+}
+
+/// Walks `dir` recursively, skipping hidden entries and not following
+/// symlinked directories so link loops cannot trap the scan.
+fn collect_audio_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in read_dir.filter_map(Result::ok) {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_audio_files(&path, files);
+        } else if is_audio_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn encode_track(track: &LibraryTrack) -> Vec<u8> {
+    let mut bytes = vec![1];
+    bytes.extend_from_slice(&track.size.to_le_bytes());
+    bytes.extend_from_slice(&track.modified.to_le_bytes());
+    match track.duration {
+        Some(duration) => {
+            let millis = duration.as_millis().min(u128::from(u64::MAX)) as u64;
+            bytes.push(1);
+            bytes.extend_from_slice(&millis.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+    bytes.extend_from_slice(&encode_metadata(&track.metadata));
+    bytes
+}
+
+fn decode_track(path: PathBuf, raw: &[u8]) -> Option<LibraryTrack> {
+    if raw.first().copied() != Some(1) || raw.len() < 18 {
+        return None;
+    }
+    let size = u64::from_le_bytes(raw[1..9].try_into().ok()?);
+    let modified = u64::from_le_bytes(raw[9..17].try_into().ok()?);
+    let (duration, rest) = match raw[17] {
+        1 => {
+            let millis = u64::from_le_bytes(raw.get(18..26)?.try_into().ok()?);
+            (Some(Duration::from_millis(millis)), &raw[26..])
+        }
+        _ => (None, &raw[18..]),
+    };
+
+    Some(LibraryTrack {
+        path,
+        size,
+        modified,
+        duration,
+        metadata: decode_metadata(rest)?,
+    })
+}
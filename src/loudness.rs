@@ -0,0 +1,263 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use rodio::{Decoder, Source};
+
+use crate::biquad::Biquad;
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+/// Gating blocks are 400 ms long and start every 100 ms.
+const SUBBLOCKS_PER_BLOCK: usize = 4;
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Integrated loudness and true peak of a whole file, measured as in
+/// EBU R128 / ITU-R BS.1770.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// In LUFS.
+    pub integrated: f32,
+    /// Linear, where 1.0 is full scale.
+    pub true_peak: f32,
+}
+
+/// Decodes `path` and measures it. Returns `None` when the file cannot be
+/// decoded or is silent throughout.
+pub fn analyze(path: &Path) -> Option<Loudness> {
+    let file = File::open(path).ok()?;
+    let mut decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let channels = usize::from(decoder.channels()).max(1);
+    let sample_rate = f64::from(decoder.sample_rate());
+    if sample_rate <= 0.0 {
+        return None;
+    }
+
+    let mut meter = Meter::new(channels, sample_rate);
+    let mut frame = vec![0.0_f32; channels];
+    'frames: loop {
+        for slot in &mut frame {
+            match decoder.next() {
+                Some(sample) => *slot = sample,
+                None => break 'frames,
+            }
+        }
+        meter.push(&frame);
+    }
+    meter.finish()
+}
+
+struct Meter {
+    filters: Vec<KWeighting>,
+    peaks: Vec<TruePeak>,
+    weights: Vec<f64>,
+    subblock_len: usize,
+    subblock_pos: usize,
+    subblock_sum: f64,
+    /// Mean square of every finished 100 ms sub-block.
+    subblocks: Vec<f64>,
+}
+
+impl Meter {
+    fn new(channels: usize, sample_rate: f64) -> Self {
+        Self {
+            filters: (0..channels)
+                .map(|_| KWeighting::new(sample_rate))
+                .collect(),
+            peaks: (0..channels).map(|_| TruePeak::new(sample_rate)).collect(),
+            weights: (0..channels)
+                .map(|channel| channel_weight(channel, channels))
+                .collect(),
+            subblock_len: ((sample_rate / 10.0).round() as usize).max(1),
+            subblock_pos: 0,
+            subblock_sum: 0.0,
+            subblocks: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, frame: &[f32]) {
+        for (channel, &sample) in frame.iter().enumerate() {
+            self.peaks[channel].push(sample);
+            let filtered = self.filters[channel].process(f64::from(sample));
+            self.subblock_sum += self.weights[channel] * filtered * filtered;
+        }
+        self.subblock_pos += 1;
+        if self.subblock_pos == self.subblock_len {
+            self.subblocks
+                .push(self.subblock_sum / self.subblock_len as f64);
+            self.subblock_pos = 0;
+            self.subblock_sum = 0.0;
+        }
+    }
+
+    fn finish(self) -> Option<Loudness> {
+        let blocks: Vec<f64> = self
+            .subblocks
+            .windows(SUBBLOCKS_PER_BLOCK)
+            .map(|window| window.iter().sum::<f64>() / SUBBLOCKS_PER_BLOCK as f64)
+            .filter(|power| loudness_of(*power) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let relative_gate = loudness_of(mean(&blocks)) + RELATIVE_GATE_LU;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|power| loudness_of(*power) > relative_gate)
+            .collect();
+        let integrated = loudness_of(mean(&gated));
+        let true_peak = self.peaks.iter().map(|peak| peak.max).fold(0.0, f32::max);
+        Some(Loudness {
+            integrated: integrated as f32,
+            true_peak,
+        })
+    }
+}
+
+fn loudness_of(power: f64) -> f64 {
+    -0.691 + 10.0 * power.max(1e-20).log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+/// BS.1770 weights for a 5.1 layout: the LFE channel is left out and the
+/// surrounds count +1.5 dB. Other layouts weigh every channel equally.
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// The K-weighting curve: a high-shelf "head" filter followed by a
+/// high-pass, with coefficients derived for any sample rate.
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(rate: f64) -> Self {
+        let f0 = 1_681.974_450_955_533;
+        let gain = 3.999_843_853_973_347;
+        let q = 0.707_175_236_955_419_6;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let f0 = 38.135_470_876_024_44;
+        let q = 0.500_327_037_323_877_3;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+/// Estimates inter-sample peaks by 4x oversampling with a windowed-sinc
+/// interpolator. Rates of 176.4 kHz and above are measured as they are.
+struct TruePeak {
+    /// One filter per interpolated phase between two input samples.
+    phases: Vec<[f32; TAPS_PER_PHASE]>,
+    history: [f32; TAPS_PER_PHASE],
+    max: f32,
+}
+
+impl TruePeak {
+    fn new(rate: f64) -> Self {
+        let phases = if rate >= 176_400.0 {
+            Vec::new()
+        } else {
+            (1..OVERSAMPLING).map(interpolation_phase).collect()
+        };
+        Self {
+            phases,
+            history: [0.0; TAPS_PER_PHASE],
+            max: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.history.rotate_right(1);
+        self.history[0] = sample;
+        self.max = self.max.max(sample.abs());
+        for phase in &self.phases {
+            let value: f32 = phase
+                .iter()
+                .zip(&self.history)
+                .map(|(tap, sample)| tap * sample)
+                .sum();
+            self.max = self.max.max(value.abs());
+        }
+    }
+}
+
+/// Taps for the point `phase / OVERSAMPLING` of the way between the two
+/// middle samples of the history, Hann-windowed and normalised to unity gain.
+fn interpolation_phase(phase: usize) -> [f32; TAPS_PER_PHASE] {
+    let half = (TAPS_PER_PHASE / 2) as f64;
+    let offset = phase as f64 / OVERSAMPLING as f64;
+    let mut taps = [0.0; TAPS_PER_PHASE];
+    for (index, tap) in taps.iter_mut().enumerate() {
+        // history[index] is `index` samples old; the interpolated point
+        // lies `half - offset` samples back.
+        let x = (half - offset) - index as f64;
+        let sinc = if x.abs() < 1e-9 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        };
+        let window = 0.5 + 0.5 * (PI * x / (half + 1.0)).cos();
+        *tap = sinc * window;
+    }
+    let sum: f64 = taps.iter().sum();
+    taps.map(|tap| (tap / sum) as f32)
+}
+
+/// `[1, integrated, true peak]`, or `[0]` for a file that has nothing to
+/// normalise with: tagged with ReplayGain already, silent or undecodable.
+pub fn encode_loudness(loudness: Option<Loudness>) -> Vec<u8> {
+    match loudness {
+        Some(loudness) => {
+            let mut bytes = Vec::with_capacity(9);
+            bytes.push(1);
+            bytes.extend_from_slice(&loudness.integrated.to_le_bytes());
+            bytes.extend_from_slice(&loudness.true_peak.to_le_bytes());
+            bytes
+        }
+        None => vec![0],
+    }
+}
+
+pub fn decode_loudness(raw: &[u8]) -> Option<Loudness> {
+    if raw.first().copied() != Some(1) || raw.len() != 9 {
+        return None;
+    }
+    Some(Loudness {
+        integrated: f32::from_le_bytes(raw[1..5].try_into().ok()?),
+        true_peak: f32::from_le_bytes(raw[5..9].try_into().ok()?),
+    })
+}
@@ -1,79 +1,728 @@
 mod app;
+mod biquad;
+mod bookmarks;
+mod chapters;
+mod cli;
+mod config;
+mod cue;
+mod eq;
+mod filter;
+mod gapless;
+mod keymap;
+mod library;
+mod loudness;
+mod metadata;
+#[cfg(feature = "mpris")]
+mod mpris;
 mod player;
+mod playlist;
+mod queue;
+mod replaygain;
+mod session;
+mod theme;
+mod timestretch;
 mod ui;
+mod visualizer;
 
 use app::App;
 use app::UiMode;
+use cli::Command;
 use color_eyre::{Result, eyre::eyre};
+use config::Config;
 use crossterm::event::{self, Event, KeyCode};
-use player::MusicPlayer;
+use cue::TrackSpan;
+use keymap::{Action, KeyOutcome};
+#[cfg(feature = "mpris")]
+use mpris::{Mpris, MprisCommand};
+use player::{MusicPlayer, PlaybackState};
+use queue::QueuedTrack;
 use ratatui::DefaultTerminal;
-use std::time::Duration;
+use session::Session;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const DEFAULT_CACHE_DIR: &str = ".mp3-tui-cache";
+const SHORT_SEEK_SECS: i64 = 5;
+const LONG_SEEK_SECS: i64 = 30;
+/// How often the session is saved while running, in case of a crash.
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+/// A saved position is only jumped to while a track is this close to its start.
+const RESUME_SKIP_WINDOW: Duration = Duration::from_secs(2);
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let args = match cli::parse(std::env::args_os().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(Command::Version) => {
+            println!("mp3-tui {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(err) => return Err(eyre!("{err}\n\n{}", cli::USAGE)),
+    };
+
+    let config = match &args.config {
+        Some(path) => Config::load_from(path)?,
+        None => Config::load()?,
+    };
+    let (start_dir, files) = split_paths(&args.paths)?;
+    let cache_dir = args
+        .cache_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR));
+
     let mut music_player = MusicPlayer::new().map_err(|err| eyre!(err.to_string()))?;
-    let mut app = App::new();
+    let mut app = App::new(start_dir, &cache_dir);
+    app.keymap = config.keymap;
+    app.set_theme(config.theme);
+    music_player.set_crossfade(config.crossfade);
+    music_player.set_replay_gain(config.replay_gain);
+    music_player.set_preserve_pitch(config.preserve_pitch);
+    app.speed_memory = config.speed_memory;
+    app.set_eq_presets(config.eq_presets);
+    music_player.set_equalizer(&app.eq.gains);
+    if let Some(volume) = app.saved_volume() {
+        music_player.set_volume(volume);
+    }
+    music_player.set_shuffle(app.saved_shuffle());
+    music_player.set_repeat(app.saved_repeat());
+    if let Some(volume) = args.volume {
+        music_player.set_volume(volume);
+    }
+    if args.shuffle {
+        music_player.set_shuffle(true);
+    }
+    if !files.is_empty() {
+        let tracks = app.queued_tracks_for(&files);
+        let result = music_player.play_queue(tracks, 0);
+        report_playback(&mut app, result);
+    } else if args.paths.is_empty()
+        && config.resume
+        && !args.no_resume
+        && let Some(session) = app.saved_session()
+    {
+        restore_session(&mut app, &mut music_player, session);
+    }
+
     let mut terminal = ratatui::init();
     let result = run(&mut terminal, &mut app, &mut music_player);
     ratatui::restore();
+    save_session(&app, &music_player);
+    remember_position(&app, &music_player);
     result
 }
 
+/// Reopens the folder and queue of the last session, with its track paused
+/// where it was. Files that have gone since are left out.
+fn restore_session(app: &mut App, music_player: &mut MusicPlayer, session: Session) {
+    if session.directory.is_dir() {
+        app.enter_directory(session.directory);
+    }
+    if let Some(playlist) = session.playlist.filter(|path| path.is_file()) {
+        app.open_playlist(playlist);
+    }
+    if let Some(selected) = &session.selected {
+        app.select_path(selected);
+    }
+
+    let Some(current) = session.current.filter(|&index| {
+        session
+            .queue
+            .get(index)
+            .is_some_and(|(path, _)| path.is_file())
+    }) else {
+        return;
+    };
+    let start = session.queue[..current]
+        .iter()
+        .filter(|(path, _)| path.is_file())
+        .count();
+    let tracks: Vec<QueuedTrack> = session
+        .queue
+        .into_iter()
+        .filter(|(path, _)| path.is_file())
+        .map(|(path, span)| match span {
+            Some(span) => app.queued_span(&path, span),
+            None => app.queued_track(&path),
+        })
+        .collect();
+    app.status = match music_player.resume_queue(tracks, start, session.position) {
+        Ok(()) => Some(format!(
+            "resumed at {} ({} when closed)",
+            ui::format_duration(session.position),
+            if session.paused { "paused" } else { "playing" }
+        )),
+        Err(err) => Some(format!("could not resume: {err}")),
+    };
+}
+
+/// Picks a long file up where it was left off, unless playback has already
+/// been moved there, as when resuming a session.
+fn resume_position(
+    app: &mut App,
+    music_player: &mut MusicPlayer,
+    path: &Path,
+    span: Option<TrackSpan>,
+) {
+    let Some(position) = app.saved_position(path, span) else {
+        return;
+    };
+    if music_player
+        .current_position()
+        .is_none_or(|current| current > RESUME_SKIP_WINDOW)
+    {
+        return;
+    }
+    match music_player.seek_to(position) {
+        Ok(()) => app.status = Some(format!("resumed at {}", ui::format_duration(position))),
+        Err(err) => app.status = Some(format!("could not resume: {err}")),
+    }
+}
+
+fn remember_position(app: &App, music_player: &MusicPlayer) {
+    if let (Some(path), Some(position)) = (
+        &music_player.current_song_path,
+        music_player.current_position(),
+    ) {
+        app.save_position(
+            path,
+            music_player.current_span,
+            position,
+            music_player.current_duration(),
+        );
+    }
+}
+
+fn save_session(app: &App, music_player: &MusicPlayer) {
+    let queue = music_player.queue();
+    let current = match music_player.state {
+        PlaybackState::Stopped => None,
+        _ => queue.current_index(),
+    };
+    app.save_session(&Session {
+        directory: app.current_path.clone(),
+        playlist: app.open_playlist.clone(),
+        selected: app.selected_entry().map(|entry| entry.path.clone()),
+        queue: queue
+            .tracks()
+            .iter()
+            .map(|track| (track.path.clone(), track.span))
+            .collect(),
+        current,
+        position: music_player.current_position().unwrap_or_default(),
+        paused: music_player.state == PlaybackState::Paused,
+    });
+}
+
+/// Sorts command-line paths into the folder to start in and the files to
+/// play. Without a directory argument the browser opens next to the first
+/// file, or in the working directory.
+fn split_paths(paths: &[PathBuf]) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let mut start_dir = None;
+    let mut files = Vec::new();
+    for path in paths {
+        let path = std::path::absolute(path)
+            .map_err(|err| eyre!("cannot resolve {}: {err}", path.display()))?;
+        if path.is_dir() {
+            if start_dir.is_some() {
+                return Err(eyre!("only one directory can be given"));
+            }
+            start_dir = Some(path);
+        } else if !path.is_file() {
+            return Err(eyre!("{} does not exist", path.display()));
+        } else if metadata::is_audio_file(&path) || playlist::is_playlist_file(&path) {
+            files.push(path);
+        } else {
+            return Err(eyre!("{} is not an audio file or playlist", path.display()));
+        }
+    }
+
+    let start_dir = start_dir
+        .or_else(|| {
+            files
+                .first()
+                .and_then(|file| file.parent())
+                .map(Path::to_path_buf)
+        })
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_else(|| PathBuf::from("/"));
+    Ok((start_dir, files))
+}
+
 pub fn run(
     terminal: &mut DefaultTerminal,
     app: &mut App,
     music_player: &mut MusicPlayer,
 ) -> Result<()> {
+    let mut last_track: Option<(PathBuf, Option<TrackSpan>)> = None;
+    // Where the track was on the previous tick, to remember once it changes.
+    let mut last_position: Option<(Duration, Option<Duration>)> = None;
+    let mut last_session_save = Instant::now();
+    #[cfg(feature = "mpris")]
+    let mut mpris = match Mpris::start() {
+        Ok(mpris) => Some(mpris),
+        Err(err) => {
+            app.status
+                .get_or_insert_with(|| format!("MPRIS unavailable: {err}"));
+            None
+        }
+    };
     loop {
+        if last_session_save.elapsed() >= SESSION_SAVE_INTERVAL {
+            save_session(app, music_player);
+            remember_position(app, music_player);
+            last_session_save = Instant::now();
+        }
         app.update_background_jobs();
-        music_player.update_state();
+        if let Err(err) = music_player.update_state() {
+            app.status = Some(format!("playback error: {err}"));
+        }
+        #[cfg(feature = "mpris")]
+        if let Some(mpris) = &mut mpris {
+            if handle_mpris(app, music_player, mpris) {
+                break;
+            }
+            mpris.update(music_player);
+        }
+        let track = music_player
+            .current_song_path
+            .clone()
+            .map(|path| (path, music_player.current_span));
+        if track != last_track {
+            if let (Some((path, span)), Some((position, duration))) = (&last_track, last_position) {
+                app.save_position(path, *span, position, duration);
+            }
+            last_track = track;
+            if let Some((path, span)) = &last_track {
+                if let Some(speed) = app.saved_speed(path) {
+                    music_player.set_speed(speed);
+                }
+                resume_position(app, music_player, path, *span);
+            }
+            app.load_bookmarks(
+                last_track
+                    .as_ref()
+                    .map(|(path, span)| (path.as_path(), *span)),
+            );
+        }
+        last_position = music_player
+            .current_position()
+            .map(|position| (position, music_player.current_duration()));
         terminal.draw(|frame| ui::render(frame, app, music_player))?;
 
-        if event::poll(Duration::from_millis(16))? {
-            if let Event::Key(key) = event::read()? {
+        if event::poll(Duration::from_millis(16))?
+            && let Event::Key(key) = event::read()?
+        {
+            if let Some(prompt) = &mut app.bookmark_prompt {
                 match key.code {
-                    KeyCode::Esc => {
-                        break;
+                    KeyCode::Esc => app.bookmark_prompt = None,
+                    KeyCode::Enter => app.finish_bookmark_prompt(),
+                    KeyCode::Backspace => {
+                        prompt.name.pop();
                     }
-                    KeyCode::Down => {
-                        app.move_down();
+                    KeyCode::Char(ch) => prompt.name.push(ch),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if app.is_editing_filter() {
+                match key.code {
+                    KeyCode::Esc => app.clear_filter(),
+                    KeyCode::Enter => app.finish_filter(),
+                    KeyCode::Backspace => app.pop_filter_char(),
+                    KeyCode::Up => app.move_up(),
+                    KeyCode::Down => app.move_down(),
+                    KeyCode::Char(ch) => app.push_filter_char(ch),
+                    _ => {}
+                }
+                continue;
+            }
+
+            let action = match app.keymap.handle(key) {
+                KeyOutcome::Action(action) => action,
+                KeyOutcome::Pending => continue,
+                KeyOutcome::Unbound => {
+                    if key.code == KeyCode::Esc {
+                        cancel(app);
                     }
-                    KeyCode::Up => {
-                        app.move_up();
+                    continue;
+                }
+            };
+
+            match action {
+                Action::Quit => {
+                    break;
+                }
+                Action::Filter if app.ui_mode == UiMode::Default => {
+                    app.start_filter();
+                }
+                Action::Down if app.bookmark_selection.is_some() => {
+                    app.move_bookmark_selection(true);
+                }
+                Action::Up if app.bookmark_selection.is_some() => {
+                    app.move_bookmark_selection(false);
+                }
+                Action::Activate if app.bookmark_selection.is_some() => {
+                    if let Some(bookmark) = app.selected_bookmark().cloned() {
+                        let result = music_player.seek_to(bookmark.position);
+                        report_seek(app, result);
+                        app.bookmark_selection = None;
                     }
-                    KeyCode::Enter => {
-                        if let Some(selected) = app.selected_entry().cloned() {
-                            if selected.is_dir {
-                                app.enter_directory(selected.path);
-                                app.status = None;
-                            } else if music_player.is_playing_track(&selected.path) {
-                                music_player.toggle_pause();
-                                app.status = None;
-                            } else {
-                                let prefetched_duration = app.cached_duration(&selected.path);
-                                match music_player
-                                    .play_file(selected.path.clone(), prefetched_duration)
-                                {
-                                    Ok(()) => app.status = None,
-                                    Err(err) => {
-                                        app.status = Some(format!("playback error: {err}"));
-                                    }
-                                }
-                            }
+                }
+                Action::Unqueue if app.bookmark_selection.is_some() => {
+                    app.delete_selected_bookmark();
+                }
+                Action::Down if app.ui_mode == UiMode::Equalizer => {
+                    app.eq.lower();
+                    apply_eq(app, music_player);
+                }
+                Action::Up if app.ui_mode == UiMode::Equalizer => {
+                    app.eq.raise();
+                    apply_eq(app, music_player);
+                }
+                Action::SeekBack if app.ui_mode == UiMode::Equalizer => {
+                    app.eq.select_previous_band();
+                }
+                Action::SeekForward if app.ui_mode == UiMode::Equalizer => {
+                    app.eq.select_next_band();
+                }
+                Action::Down => {
+                    app.move_down();
+                }
+                Action::Up => {
+                    app.move_up();
+                }
+                Action::Activate if app.ui_mode == UiMode::Library => {
+                    if let Some((tracks, start)) = app.activate_library_selection() {
+                        let result = music_player.play_queue(tracks, start);
+                        report_playback(app, result);
+                    }
+                }
+                Action::LibraryBack if app.ui_mode == UiMode::Library => {
+                    app.library_browser.go_up();
+                }
+                Action::LibraryCategory if app.ui_mode == UiMode::Library => {
+                    app.library_browser.cycle_category();
+                }
+                Action::Activate => {
+                    if let Some(selected) = app.selected_entry().cloned() {
+                        if selected.is_dir {
+                            app.enter_directory(selected.path);
+                            app.status = None;
+                        } else if selected.is_playlist {
+                            app.status = None;
+                            app.open_playlist(selected.path);
+                        } else if music_player.is_playing_track(&selected.path, selected.span()) {
+                            music_player.toggle_pause();
+                            app.status = None;
+                        } else {
+                            let (tracks, start) = app.folder_queue();
+                            let result = music_player.play_queue(tracks, start);
+                            report_playback(app, result);
                         }
                     }
-                    KeyCode::Char('1') => {
-                        app.ui_mode = UiMode::Default;
+                }
+                Action::PlayFromSelected => {
+                    let tracks = app.tracks_from_selected();
+                    if tracks.is_empty() {
+                        app.status = Some("no tracks to play".to_string());
+                    } else {
+                        let result = music_player.play_queue(tracks, 0);
+                        report_playback(app, result);
                     }
-                    KeyCode::Char('2') => {
-                        app.ui_mode = UiMode::FullScreenPlayer;
+                }
+                Action::Enqueue => {
+                    if let Some(selected) = app
+                        .selected_entry()
+                        .filter(|entry| entry.is_track())
+                        .cloned()
+                    {
+                        let track = app.queued_entry(&selected);
+                        app.status = Some(format!("queued {}", selected.name));
+                        music_player.enqueue(track);
                     }
-                    _ => {}
                 }
+                Action::EnqueueNext => {
+                    if let Some(selected) = app
+                        .selected_entry()
+                        .filter(|entry| entry.is_track())
+                        .cloned()
+                    {
+                        let track = app.queued_entry(&selected);
+                        app.status = Some(format!("playing next: {}", selected.name));
+                        music_player.enqueue_next(track);
+                    }
+                }
+                Action::Unqueue => {
+                    if let Some(selected) = app.selected_entry().cloned() {
+                        app.status = match music_player
+                            .upcoming_queue_position(&selected.path, selected.span())
+                        {
+                            Some(index) => {
+                                music_player.remove_from_queue(index);
+                                Some(format!("removed {} from queue", selected.name))
+                            }
+                            None => Some(format!("{} is not queued", selected.name)),
+                        };
+                    }
+                }
+                Action::ClearQueue => {
+                    music_player.clear_queue();
+                    app.status = Some("queue cleared".to_string());
+                }
+                Action::Next => {
+                    let result = music_player.next();
+                    report_playback(app, result);
+                }
+                Action::Previous => {
+                    let result = music_player.previous();
+                    report_playback(app, result);
+                }
+                Action::NextChapter => {
+                    let result = music_player.next_chapter();
+                    report_seek(app, result);
+                }
+                Action::PreviousChapter => {
+                    let result = music_player.previous_chapter();
+                    report_seek(app, result);
+                }
+                Action::SeekBack => {
+                    let result = music_player.seek_relative(-SHORT_SEEK_SECS);
+                    report_seek(app, result);
+                }
+                Action::SeekForward => {
+                    let result = music_player.seek_relative(SHORT_SEEK_SECS);
+                    report_seek(app, result);
+                }
+                Action::SeekBackLong => {
+                    let result = music_player.seek_relative(-LONG_SEEK_SECS);
+                    report_seek(app, result);
+                }
+                Action::SeekForwardLong => {
+                    let result = music_player.seek_relative(LONG_SEEK_SECS);
+                    report_seek(app, result);
+                }
+                Action::Jump(tenths) => {
+                    let result = music_player.seek_to_fraction(f64::from(tenths) / 10.0);
+                    report_seek(app, result);
+                }
+                Action::VolumeUp => {
+                    music_player.volume_up();
+                    app.save_volume(music_player.volume());
+                }
+                Action::VolumeDown => {
+                    music_player.volume_down();
+                    app.save_volume(music_player.volume());
+                }
+                Action::Mute => {
+                    music_player.toggle_mute();
+                }
+                Action::Shuffle => {
+                    music_player.toggle_shuffle();
+                    let queue = music_player.queue();
+                    app.save_playback_modes(queue.shuffle(), queue.repeat());
+                }
+                Action::Repeat => {
+                    music_player.cycle_repeat();
+                    let queue = music_player.queue();
+                    app.save_playback_modes(queue.shuffle(), queue.repeat());
+                }
+                Action::SaveQueue => {
+                    let tracks = music_player.queue().tracks().to_vec();
+                    app.save_playlist("queue", &tracks);
+                }
+                Action::SaveFolder => {
+                    let (tracks, _) = app.folder_queue();
+                    let name = app.current_folder_name();
+                    app.save_playlist(&name, &tracks);
+                }
+                Action::ToggleLibraryRoot => {
+                    app.toggle_library_root();
+                }
+                Action::RescanLibrary => {
+                    app.rescan_library();
+                }
+                Action::SplitView => {
+                    app.ui_mode = UiMode::Default;
+                }
+                Action::PlayerView => {
+                    app.ui_mode = UiMode::FullScreenPlayer;
+                }
+                Action::LibraryView => {
+                    app.open_library_view();
+                }
+                Action::CycleTheme => {
+                    app.cycle_theme();
+                }
+                Action::EqView => {
+                    app.ui_mode = UiMode::Equalizer;
+                }
+                Action::EqPreset => {
+                    app.cycle_eq_preset();
+                    apply_eq(app, music_player);
+                }
+                Action::SpeedUp | Action::SpeedDown => {
+                    if action == Action::SpeedUp {
+                        music_player.speed_up();
+                    } else {
+                        music_player.speed_down();
+                    }
+                    let speed = music_player.speed();
+                    if let Some(path) = &music_player.current_song_path {
+                        app.save_speed(path, speed);
+                    }
+                    app.status = Some(format!("speed: {speed:.1}x"));
+                }
+                Action::AddBookmark => match music_player.current_position() {
+                    Some(position) => app.start_bookmark_prompt(position),
+                    None => app.status = Some("nothing playing to bookmark".to_string()),
+                },
+                Action::Bookmarks => {
+                    app.toggle_bookmark_list();
+                }
+                Action::Help => {
+                    app.show_help = !app.show_help;
+                }
+                Action::TogglePitch => {
+                    let preserve = !music_player.preserves_pitch();
+                    music_player.set_preserve_pitch(preserve);
+                    app.status = Some(
+                        if preserve {
+                            "pitch: kept at any speed"
+                        } else {
+                            "pitch: follows speed"
+                        }
+                        .to_string(),
+                    );
+                }
+                _ => {}
             }
         }
     }
 
     Ok(())
 }
+
+/// Esc when it is not bound to anything: drops a half-typed key sequence,
+/// then an applied filter, then steps out of a library level.
+fn cancel(app: &mut App) {
+    if app.keymap.cancel_pending() {
+        return;
+    }
+    if app.bookmark_selection.take().is_some() {
+        return;
+    }
+    if app.show_help {
+        app.show_help = false;
+        return;
+    }
+    match app.ui_mode {
+        UiMode::Default if app.filter.is_some() => app.clear_filter(),
+        UiMode::Library => app.library_browser.go_up(),
+        _ => {}
+    }
+}
+
+/// Carries out requests from MPRIS clients. Returns true when one asks the
+/// player to quit.
+#[cfg(feature = "mpris")]
+fn handle_mpris(app: &mut App, music_player: &mut MusicPlayer, mpris: &Mpris) -> bool {
+    while let Some(command) = mpris.try_command() {
+        match command {
+            MprisCommand::Play | MprisCommand::PlayPause
+                if music_player.state == PlaybackState::Stopped =>
+            {
+                let (tracks, start) = app.folder_queue();
+                if !tracks.is_empty() {
+                    let result = music_player.play_queue(tracks, start);
+                    report_playback(app, result);
+                }
+            }
+            MprisCommand::Play => music_player.resume(),
+            MprisCommand::Pause => music_player.pause(),
+            MprisCommand::PlayPause => music_player.toggle_pause(),
+            MprisCommand::Stop => music_player.stop(),
+            MprisCommand::Next => {
+                let result = music_player.next();
+                report_playback(app, result);
+            }
+            MprisCommand::Previous => {
+                let result = music_player.previous();
+                report_playback(app, result);
+            }
+            MprisCommand::Seek(offset) => {
+                let Some(position) = music_player.current_position() else {
+                    continue;
+                };
+                let distance = Duration::from_micros(offset.unsigned_abs());
+                let target = if offset < 0 {
+                    position.saturating_sub(distance)
+                } else {
+                    position + distance
+                };
+                // Seeking past the end moves on to the next track.
+                if music_player
+                    .current_duration()
+                    .is_some_and(|total| target >= total)
+                {
+                    let result = music_player.next();
+                    report_playback(app, result);
+                } else {
+                    let result = music_player.seek_to(target);
+                    report_seek(app, result);
+                }
+            }
+            MprisCommand::SetPosition(position) => {
+                if music_player
+                    .current_duration()
+                    .is_none_or(|total| position <= total)
+                {
+                    let result = music_player.seek_to(position);
+                    report_seek(app, result);
+                }
+            }
+            MprisCommand::SetVolume(volume) => {
+                music_player.set_volume(volume as f32);
+                app.save_volume(music_player.volume());
+            }
+            MprisCommand::SetRate(rate) => {
+                music_player.set_speed(rate as f32);
+                if let Some(path) = &music_player.current_song_path {
+                    app.save_speed(path, music_player.speed());
+                }
+            }
+            MprisCommand::SetShuffle(shuffle) => {
+                music_player.set_shuffle(shuffle);
+                app.save_playback_modes(shuffle, music_player.queue().repeat());
+            }
+            MprisCommand::SetRepeat(repeat) => {
+                music_player.set_repeat(repeat);
+                app.save_playback_modes(music_player.queue().shuffle(), repeat);
+            }
+            MprisCommand::Quit => return true,
+        }
+    }
+    false
+}
+
+fn apply_eq(app: &App, music_player: &MusicPlayer) {
+    music_player.set_equalizer(&app.eq.gains);
+    app.save_eq();
+}
+
+fn report_playback(app: &mut App, result: Result<(), Box<dyn std::error::Error + Send + Sync>>) {
+    app.status = match result {
+        Ok(()) => None,
+        Err(err) => Some(format!("playback error: {err}")),
+    };
+}
+
+fn report_seek(app: &mut App, result: Result<(), Box<dyn std::error::Error + Send + Sync>>) {
+    if let Err(err) = result {
+        app.status = Some(err.to_string());
+    }
+}
@@ -0,0 +1,354 @@
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::{Hint, ProbeResult};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+}
+
+impl TrackMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// "Artist - Title" when both are tagged, the title alone otherwise.
+    pub fn display_title(&self) -> Option<String> {
+        let title = self.title.as_deref()?;
+        Some(match self.artist.as_deref() {
+            Some(artist) => format!("{artist} - {title}"),
+            None => title.to_string(),
+        })
+    }
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            matches!(
+                ext.to_ascii_lowercase().as_str(),
+                "mp3" | "wav" | "flac" | "ogg" | "m4a" | "m4b" | "aac"
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// Reads ID3v2, Vorbis comment and MP4 tags. Returns `None` when the file
+/// cannot be probed; an untagged file yields an empty `TrackMetadata`.
+pub fn read_metadata(path: &Path) -> Option<TrackMetadata> {
+    read_track_info(path).map(|(metadata, _)| metadata)
+}
+
+/// Like [`read_metadata`], plus the duration declared by the container. This
+/// is cheap compared to `probe_duration`, but not every file declares one.
+pub fn read_track_info(path: &Path) -> Option<(TrackMetadata, Option<Duration>)> {
+    let mut probed = probe(path)?;
+
+    let mut metadata = TrackMetadata::default();
+    for revision in current_revisions(&mut probed) {
+        apply_revision(&mut metadata, &revision);
+    }
+
+    let duration = probed.format.default_track().and_then(|track| {
+        let params = &track.codec_params;
+        let frames = params.n_frames?;
+        match (params.time_base, params.sample_rate) {
+            (Some(time_base), _) => {
+                let time = time_base.calc_time(frames);
+                Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+            }
+            (None, Some(rate)) if rate > 0 => {
+                Some(Duration::from_secs_f64(frames as f64 / f64::from(rate)))
+            }
+            _ => None,
+        }
+    });
+    Some((metadata, duration))
+}
+
+/// Encoder delay and padding, in frames, that are not part of the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GaplessInfo {
+    pub delay: u64,
+    /// Frames of real audio after the delay, when the encoder recorded it.
+    pub valid_frames: Option<u64>,
+}
+
+/// The iTunes `iTunSMPB` tag found in AAC/ALAC files. MP3 files need no
+/// help: the decoder already trims them using the LAME header.
+fn gapless_info(revisions: &[MetadataRevision]) -> Option<GaplessInfo> {
+    revisions
+        .iter()
+        .flat_map(|revision| revision.tags())
+        .find(|tag| tag.key.to_ascii_lowercase().ends_with("itunsmpb"))
+        .and_then(|tag| parse_itunsmpb(&tag.value.to_string()))
+}
+
+/// `" 00000000 00000840 000001CA 00000000003F31F6 ..."`: reserved, delay,
+/// padding and the original frame count, all hex.
+fn parse_itunsmpb(value: &str) -> Option<GaplessInfo> {
+    let mut fields = value
+        .split_whitespace()
+        .map(|field| u64::from_str_radix(field, 16).ok());
+    let _reserved = fields.next()??;
+    let delay = fields.next()??;
+    let _padding = fields.next()??;
+    let valid_frames = fields.next().flatten().filter(|frames| *frames > 0);
+    if delay == 0 && valid_frames.is_none() {
+        return None;
+    }
+    Some(GaplessInfo {
+        delay,
+        valid_frames,
+    })
+}
+
+/// ReplayGain tags as written by the ripper: gains in dB, peaks as linear
+/// sample values where 1.0 is full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGainTags {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGainTags {
+    pub fn is_empty(&self) -> bool {
+        self.track_gain.is_none() && self.album_gain.is_none()
+    }
+}
+
+/// Tags that change how a file sounds rather than what is shown for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlaybackTags {
+    pub replay_gain: Option<ReplayGainTags>,
+    pub gapless: Option<GaplessInfo>,
+}
+
+/// Reads [`TrackMetadata`] and [`PlaybackTags`] in a single probe.
+pub fn read_tags(path: &Path) -> Option<(TrackMetadata, PlaybackTags)> {
+    let mut probed = probe(path)?;
+    let revisions = current_revisions(&mut probed);
+    let mut metadata = TrackMetadata::default();
+    for revision in &revisions {
+        apply_revision(&mut metadata, revision);
+    }
+    let playback = PlaybackTags {
+        replay_gain: replay_gain_tags(&revisions),
+        gapless: gapless_info(&revisions),
+    };
+    Some((metadata, playback))
+}
+
+/// Reads `REPLAYGAIN_*` tags from ID3v2 `TXXX` frames, Vorbis comments and
+/// MP4 freeform atoms. Returns `None` when the file has no gain tags.
+pub fn read_replay_gain(path: &Path) -> Option<ReplayGainTags> {
+    let mut probed = probe(path)?;
+    replay_gain_tags(&current_revisions(&mut probed))
+}
+
+fn replay_gain_tags(revisions: &[MetadataRevision]) -> Option<ReplayGainTags> {
+    let mut tags = ReplayGainTags::default();
+    for revision in revisions {
+        for tag in revision.tags() {
+            // Symphonia only maps the upper-case TXXX descriptions, so the
+            // raw key is checked as well.
+            let key = tag.key.to_ascii_lowercase();
+            let slot = match tag.std_key {
+                Some(StandardTagKey::ReplayGainTrackGain) => &mut tags.track_gain,
+                Some(StandardTagKey::ReplayGainTrackPeak) => &mut tags.track_peak,
+                Some(StandardTagKey::ReplayGainAlbumGain) => &mut tags.album_gain,
+                Some(StandardTagKey::ReplayGainAlbumPeak) => &mut tags.album_peak,
+                _ if key.ends_with("replaygain_track_gain") => &mut tags.track_gain,
+                _ if key.ends_with("replaygain_track_peak") => &mut tags.track_peak,
+                _ if key.ends_with("replaygain_album_gain") => &mut tags.album_gain,
+                _ if key.ends_with("replaygain_album_peak") => &mut tags.album_peak,
+                _ => continue,
+            };
+            if let Some(value) = leading_float(&tag.value.to_string()) {
+                *slot = Some(value);
+            }
+        }
+    }
+    (!tags.is_empty()).then_some(tags)
+}
+
+/// Parses "-6.52 dB" as -6.52 and "0.988553" as 0.988553.
+fn leading_float(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '-' | '.')))
+        .unwrap_or(value.len());
+    value[..end]
+        .parse()
+        .ok()
+        .filter(|value: &f32| value.is_finite())
+}
+
+fn probe(path: &Path) -> Option<ProbeResult> {
+    let file = File::open(path).ok()?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()
+}
+
+/// Tags found while probing (e.g. ID3v2 in front of the stream) come first,
+/// then those of the container, so container tags win when both are set.
+fn current_revisions(probed: &mut ProbeResult) -> Vec<MetadataRevision> {
+    let mut revisions = Vec::new();
+    if let Some(probed_metadata) = probed.metadata.get()
+        && let Some(revision) = probed_metadata.current()
+    {
+        revisions.push(revision.clone());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        revisions.push(revision.clone());
+    }
+    revisions
+}
+
+fn apply_revision(metadata: &mut TrackMetadata, revision: &MetadataRevision) {
+    for tag in revision.tags() {
+        let Some(key) = tag.std_key else {
+            continue;
+        };
+        let value = tag.value.to_string().trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+
+        match key {
+            StandardTagKey::TrackTitle => metadata.title = Some(value),
+            StandardTagKey::Artist => metadata.artist = Some(value),
+            StandardTagKey::AlbumArtist if metadata.artist.is_none() => {
+                metadata.artist = Some(value);
+            }
+            StandardTagKey::Album => metadata.album = Some(value),
+            StandardTagKey::TrackNumber => metadata.track_number = leading_number(&value),
+            StandardTagKey::Date | StandardTagKey::ReleaseDate if metadata.year.is_none() => {
+                metadata.year = leading_number(&value);
+            }
+            StandardTagKey::Genre => metadata.genre = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// Parses "3/12" as 3 and "2001-05-01" as 2001.
+fn leading_number(value: &str) -> Option<u32> {
+    let digits: String = value.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+pub fn encode_metadata(metadata: &TrackMetadata) -> Vec<u8> {
+    let mut bytes = vec![1];
+    for text in [&metadata.title, &metadata.artist, &metadata.album] {
+        encode_text(&mut bytes, text.as_deref());
+    }
+    for number in [metadata.track_number, metadata.year] {
+        encode_number(&mut bytes, number);
+    }
+    encode_text(&mut bytes, metadata.genre.as_deref());
+    bytes
+}
+
+pub fn decode_metadata(raw: &[u8]) -> Option<TrackMetadata> {
+    let (&version, mut rest) = raw.split_first()?;
+    if version != 1 {
+        return None;
+    }
+
+    let title = decode_text(&mut rest)?;
+    let artist = decode_text(&mut rest)?;
+    let album = decode_text(&mut rest)?;
+    let track_number = decode_number(&mut rest)?;
+    let year = decode_number(&mut rest)?;
+    let genre = decode_text(&mut rest)?;
+    Some(TrackMetadata {
+        title,
+        artist,
+        album,
+        track_number,
+        year,
+        genre,
+    })
+}
+
+pub fn encode_text(bytes: &mut Vec<u8>, text: Option<&str>) {
+    match text {
+        Some(text) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+pub fn encode_number(bytes: &mut Vec<u8>, number: Option<u32>) {
+    match number {
+        Some(number) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&number.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+pub fn decode_text(raw: &mut &[u8]) -> Option<Option<String>> {
+    if !take_flag(raw)? {
+        return Some(None);
+    }
+    let len = take_u32(raw)? as usize;
+    if raw.len() < len {
+        return None;
+    }
+    let (text, rest) = raw.split_at(len);
+    *raw = rest;
+    Some(Some(String::from_utf8_lossy(text).to_string()))
+}
+
+pub fn decode_number(raw: &mut &[u8]) -> Option<Option<u32>> {
+    if !take_flag(raw)? {
+        return Some(None);
+    }
+    take_u32(raw).map(Some)
+}
+
+pub fn take_flag(raw: &mut &[u8]) -> Option<bool> {
+    let (&flag, rest) = raw.split_first()?;
+    *raw = rest;
+    Some(flag == 1)
+}
+
+fn take_u32(raw: &mut &[u8]) -> Option<u32> {
+    if raw.len() < 4 {
+        return None;
+    }
+    let (number, rest) = raw.split_at(4);
+    *raw = rest;
+    Some(u32::from_le_bytes(number.try_into().ok()?))
+}
@@ -0,0 +1,763 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+use zbus::fdo;
+use zbus::interface;
+use zbus::names::BusName;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+use crate::cue::TrackSpan;
+use crate::metadata::TrackMetadata;
+use crate::player::{MusicPlayer, PlaybackState};
+use crate::queue::RepeatMode;
+use crate::timestretch::{MAX_SPEED, MIN_SPEED};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.mp3_tui";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+/// A position this far from where playback should have got to since the
+/// last tick is announced as a seek.
+const SEEK_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// A request from another program, carried out by the main loop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MprisCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    /// Moves the position by this many microseconds, which may be negative.
+    Seek(i64),
+    SetPosition(Duration),
+    SetVolume(f64),
+    SetRate(f64),
+    SetShuffle(bool),
+    SetRepeat(RepeatMode),
+    Quit,
+}
+
+/// The player as seen from the bus, refreshed every tick.
+#[derive(Debug, Clone)]
+struct Status {
+    playback: &'static str,
+    repeat: RepeatMode,
+    rate: f64,
+    shuffle: bool,
+    volume: f64,
+    track_id: String,
+    metadata: HashMap<String, OwnedValue>,
+    /// Microseconds into the track.
+    position: i64,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self {
+            playback: "Stopped",
+            repeat: RepeatMode::Off,
+            rate: 1.0,
+            shuffle: false,
+            volume: 1.0,
+            track_id: NO_TRACK.to_string(),
+            metadata: HashMap::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Status {
+    fn has_track(&self) -> bool {
+        self.track_id != NO_TRACK
+    }
+
+    fn lock(status: &Mutex<Status>) -> MutexGuard<'_, Status> {
+        status.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The `org.mpris.MediaPlayer2` and `.Player` interfaces on the session bus,
+/// so media keys, status bars and `playerctl` can control playback.
+pub struct Mpris {
+    connection: Connection,
+    commands: Receiver<MprisCommand>,
+    status: Arc<Mutex<Status>>,
+    track: Option<(PathBuf, Option<TrackSpan>)>,
+    /// Bumped for every new track, to give each its own track ID.
+    track_count: u64,
+    duration: Option<Duration>,
+    tags: Option<TrackMetadata>,
+    last_position: Option<(Duration, Instant)>,
+}
+
+impl Mpris {
+    pub fn start() -> zbus::Result<Self> {
+        Self::serve(Builder::session()?)
+    }
+
+    fn serve(builder: Builder<'_>) -> zbus::Result<Self> {
+        let (sender, commands) = mpsc::channel();
+        let status = Arc::new(Mutex::new(Status::default()));
+        let connection = builder
+            .serve_at(
+                OBJECT_PATH,
+                Root {
+                    commands: sender.clone(),
+                },
+            )?
+            .serve_at(
+                OBJECT_PATH,
+                Player {
+                    commands: sender,
+                    status: status.clone(),
+                },
+            )?
+            .build()?;
+        // Another instance already has the name; the spec asks for a unique
+        // suffix rather than queueing for it.
+        if connection.request_name(BUS_NAME).is_err() {
+            connection.request_name(format!("{BUS_NAME}.instance{}", std::process::id()))?;
+        }
+        Ok(Self {
+            connection,
+            commands,
+            status,
+            track: None,
+            track_count: 0,
+            duration: None,
+            tags: None,
+            last_position: None,
+        })
+    }
+
+    pub fn try_command(&self) -> Option<MprisCommand> {
+        self.commands.try_recv().ok()
+    }
+
+    /// Publishes the player's state, signalling whatever changed since the
+    /// last call and any jump in the position.
+    pub fn update(&mut self, player: &MusicPlayer) {
+        let track = player
+            .current_song_path
+            .clone()
+            .map(|path| (path, player.current_span));
+        let new_track = track != self.track;
+        if new_track {
+            self.track = track;
+            self.track_count += 1;
+            self.last_position = None;
+        }
+
+        let position = player.current_position();
+        let seeked = match (self.last_position, position) {
+            (Some((last, at)), Some(now)) => {
+                let expected = if player.state == PlaybackState::Playing {
+                    last + at.elapsed().mul_f32(player.speed())
+                } else {
+                    last
+                };
+                now.abs_diff(expected) > SEEK_TOLERANCE
+            }
+            _ => false,
+        };
+        self.last_position = position.map(|position| (position, Instant::now()));
+
+        let mut next = Status {
+            playback: match player.state {
+                PlaybackState::Playing => "Playing",
+                PlaybackState::Paused => "Paused",
+                PlaybackState::Stopped => "Stopped",
+            },
+            repeat: player.queue().repeat(),
+            rate: f64::from(player.speed()),
+            shuffle: player.queue().shuffle(),
+            volume: if player.is_muted() {
+                0.0
+            } else {
+                f64::from(player.volume())
+            },
+            track_id: match &self.track {
+                Some(_) => format!("{OBJECT_PATH}/track/{}", self.track_count),
+                None => NO_TRACK.to_string(),
+            },
+            metadata: HashMap::new(),
+            position: position.map_or(0, micros),
+        };
+        // The duration and tags may only be known a moment after the track
+        // starts.
+        let metadata_changed = new_track
+            || player.current_duration() != self.duration
+            || player.current_metadata != self.tags;
+        if metadata_changed {
+            self.duration = player.current_duration();
+            self.tags.clone_from(&player.current_metadata);
+            if let Some((path, _)) = &self.track {
+                next.metadata =
+                    track_metadata(path, self.duration, self.tags.as_ref(), &next.track_id);
+            }
+        }
+        self.publish(next, metadata_changed, seeked);
+    }
+
+    /// Replaces the published status with `next`, keeping the old metadata
+    /// unless it changed, and sends the signals for the difference.
+    fn publish(&self, mut next: Status, metadata_changed: bool, seeked: bool) {
+        let mut status = Status::lock(&self.status);
+        if !metadata_changed {
+            next.metadata = std::mem::take(&mut status.metadata);
+        }
+
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        if next.playback != status.playback {
+            changed.insert("PlaybackStatus", Value::from(next.playback));
+        }
+        if next.repeat != status.repeat {
+            changed.insert("LoopStatus", Value::from(loop_status(next.repeat)));
+        }
+        if next.rate != status.rate {
+            changed.insert("Rate", Value::from(next.rate));
+        }
+        if next.shuffle != status.shuffle {
+            changed.insert("Shuffle", Value::from(next.shuffle));
+        }
+        if next.volume != status.volume {
+            changed.insert("Volume", Value::from(next.volume));
+        }
+        if metadata_changed {
+            changed.insert("Metadata", Value::from(next.metadata.clone()));
+        }
+        if next.has_track() != status.has_track() {
+            for capability in [
+                "CanGoNext",
+                "CanGoPrevious",
+                "CanPlay",
+                "CanPause",
+                "CanSeek",
+            ] {
+                changed.insert(capability, Value::from(next.has_track()));
+            }
+        }
+        *status = next;
+        let position = status.position;
+        drop(status);
+
+        if !changed.is_empty() {
+            let _ = self.connection.emit_signal(
+                None::<BusName>,
+                OBJECT_PATH,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &(PLAYER_INTERFACE, changed, Vec::<&str>::new()),
+            );
+        }
+        if seeked {
+            let _ = self.connection.emit_signal(
+                None::<BusName>,
+                OBJECT_PATH,
+                PLAYER_INTERFACE,
+                "Seeked",
+                &(position,),
+            );
+        }
+    }
+}
+
+struct Root {
+    commands: Sender<MprisCommand>,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {
+        let _ = self.commands.send(MprisCommand::Quit);
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn identity(&self) -> String {
+        "mp3-tui".to_string()
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct Player {
+    commands: Sender<MprisCommand>,
+    status: Arc<Mutex<Status>>,
+}
+
+impl Player {
+    fn send(&self, command: MprisCommand) {
+        let _ = self.commands.send(command);
+    }
+
+    fn status(&self) -> MutexGuard<'_, Status> {
+        Status::lock(&self.status)
+    }
+}
+
+/// Settable properties update the published status straight away, so the
+/// change signal zbus sends after a setter carries the new value.
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.send(MprisCommand::Next);
+    }
+
+    fn previous(&self) {
+        self.send(MprisCommand::Previous);
+    }
+
+    fn pause(&self) {
+        self.send(MprisCommand::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.send(MprisCommand::PlayPause);
+    }
+
+    fn stop(&self) {
+        self.send(MprisCommand::Stop);
+    }
+
+    fn play(&self) {
+        self.send(MprisCommand::Play);
+    }
+
+    fn seek(&self, offset: i64) {
+        self.send(MprisCommand::Seek(offset));
+    }
+
+    /// Ignored unless `track_id` is still the current track, as the spec
+    /// asks.
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        if track_id.as_str() == self.status().track_id && position >= 0 {
+            let position = Duration::from_micros(position.unsigned_abs());
+            self.send(MprisCommand::SetPosition(position));
+        }
+    }
+
+    fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "opening URIs is not supported".to_string(),
+        ))
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        self.status().playback.to_string()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> String {
+        loop_status(self.status().repeat).to_string()
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&mut self, value: String) -> zbus::Result<()> {
+        let repeat = match value.as_str() {
+            "None" => RepeatMode::Off,
+            "Track" => RepeatMode::One,
+            "Playlist" => RepeatMode::All,
+            _ => {
+                return Err(
+                    fdo::Error::InvalidArgs(format!("unknown loop status `{value}`")).into(),
+                );
+            }
+        };
+        self.status().repeat = repeat;
+        self.send(MprisCommand::SetRepeat(repeat));
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        self.status().rate
+    }
+
+    #[zbus(property)]
+    fn set_rate(&mut self, value: f64) {
+        // A rate of zero should pause, but pausing is left to `Pause`.
+        if value.is_finite() && value > 0.0 {
+            let rate = value.clamp(f64::from(MIN_SPEED), f64::from(MAX_SPEED));
+            self.status().rate = rate;
+            self.send(MprisCommand::SetRate(rate));
+        }
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.status().shuffle
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&mut self, value: bool) {
+        self.status().shuffle = value;
+        self.send(MprisCommand::SetShuffle(value));
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        self.status().metadata.clone()
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.status().volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        let volume = value.clamp(0.0, 1.0);
+        self.status().volume = volume;
+        self.send(MprisCommand::SetVolume(volume));
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.status().position
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn minimum_rate(&self) -> f64 {
+        f64::from(MIN_SPEED)
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn maximum_rate(&self) -> f64 {
+        f64::from(MAX_SPEED)
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.status().has_track()
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.status().has_track()
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        self.status().has_track()
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        self.status().has_track()
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.status().has_track()
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn loop_status(repeat: RepeatMode) -> &'static str {
+    match repeat {
+        RepeatMode::Off => "None",
+        RepeatMode::One => "Track",
+        RepeatMode::All => "Playlist",
+    }
+}
+
+/// The `xesam:` tags of the current track, under its `mpris:trackid`.
+fn track_metadata(
+    path: &Path,
+    duration: Option<Duration>,
+    tags: Option<&TrackMetadata>,
+    track_id: &str,
+) -> HashMap<String, OwnedValue> {
+    let mut metadata = HashMap::new();
+    let mut insert = |key: &str, value: Value| {
+        if let Ok(value) = OwnedValue::try_from(value) {
+            metadata.insert(key.to_string(), value);
+        }
+    };
+
+    if let Ok(track_id) = ObjectPath::try_from(track_id) {
+        insert("mpris:trackid", Value::from(track_id));
+    }
+    if let Some(duration) = duration {
+        insert("mpris:length", Value::from(micros(duration)));
+    }
+    insert("xesam:url", Value::from(file_url(path)));
+    let tags = tags.cloned().unwrap_or_default();
+    let title = tags.title.or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+    });
+    if let Some(title) = title {
+        insert("xesam:title", Value::from(title));
+    }
+    if let Some(artist) = tags.artist {
+        insert("xesam:artist", Value::from(vec![artist]));
+    }
+    if let Some(album) = tags.album {
+        insert("xesam:album", Value::from(album));
+    }
+    if let Some(genre) = tags.genre {
+        insert("xesam:genre", Value::from(vec![genre]));
+    }
+    if let Some(number) = tags
+        .track_number
+        .and_then(|number| i32::try_from(number).ok())
+    {
+        insert("xesam:trackNumber", Value::from(number));
+    }
+    metadata
+}
+
+fn micros(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
+
+/// `file://` URL of `path`, with everything but unreserved characters and
+/// slashes percent-encoded.
+fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            url.push(char::from(byte));
+        } else {
+            url.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::thread;
+
+    use zbus::blocking::Proxy;
+    use zbus::blocking::proxy::Builder as ProxyBuilder;
+    use zbus::proxy::CacheProperties;
+
+    use super::*;
+
+    /// A private `dbus-daemon` on a temporary socket, stopped and removed
+    /// when dropped.
+    struct Bus {
+        daemon: Child,
+        socket: PathBuf,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Option<Self> {
+            let socket = std::env::temp_dir().join(format!("mp3-tui-mpris-{}", std::process::id()));
+            let mut daemon = Command::new("dbus-daemon")
+                .arg("--session")
+                .arg("--nofork")
+                .arg("--print-address")
+                .arg(format!("--address=unix:path={}", socket.display()))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            let stdout = daemon.stdout.take()?;
+            let _ = BufReader::new(stdout).read_line(&mut address);
+            let bus = Self {
+                daemon,
+                socket,
+                address: address.trim().to_string(),
+            };
+            (!bus.address.is_empty()).then_some(bus)
+        }
+
+        fn connect(&self) -> Connection {
+            Builder::address(self.address.as_str())
+                .unwrap()
+                .build()
+                .unwrap()
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+            let _ = std::fs::remove_file(&self.socket);
+        }
+    }
+
+    fn player_proxy(connection: &Connection) -> Proxy<'static> {
+        ProxyBuilder::new(connection)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .interface(PLAYER_INTERFACE)
+            .unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn player_interface_on_a_private_bus() {
+        let Some(bus) = Bus::start() else {
+            eprintln!("dbus-daemon is not available; skipping");
+            return;
+        };
+        let mpris = Mpris::serve(Builder::address(bus.address.as_str()).unwrap()).unwrap();
+        let client = bus.connect();
+        let player = player_proxy(&client);
+
+        assert_eq!(
+            player.get_property::<String>("PlaybackStatus").unwrap(),
+            "Stopped"
+        );
+        assert!(
+            player
+                .get_property::<HashMap<String, OwnedValue>>("Metadata")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(!player.get_property::<bool>("CanSeek").unwrap());
+
+        let track_id = format!("{OBJECT_PATH}/track/1");
+        let tags = TrackMetadata {
+            artist: Some("Artist".to_string()),
+            track_number: Some(3),
+            ..TrackMetadata::default()
+        };
+        let metadata = track_metadata(
+            Path::new("/music/A Song.mp3"),
+            Some(Duration::from_secs(180)),
+            Some(&tags),
+            &track_id,
+        );
+        let status = Status {
+            playback: "Playing",
+            volume: 0.8,
+            track_id: track_id.clone(),
+            metadata,
+            position: 10_000_000,
+            ..Status::default()
+        };
+        mpris.publish(status, true, false);
+
+        assert_eq!(
+            player.get_property::<String>("PlaybackStatus").unwrap(),
+            "Playing"
+        );
+        assert!(player.get_property::<bool>("CanSeek").unwrap());
+        let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").unwrap();
+        let field = |key: &str| Value::clone(&metadata[key]);
+        assert_eq!(
+            field("mpris:trackid"),
+            Value::from(ObjectPath::try_from(track_id.as_str()).unwrap())
+        );
+        assert_eq!(field("mpris:length"), Value::from(180_000_000i64));
+        assert_eq!(field("xesam:title"), Value::from("A Song"));
+        assert_eq!(field("xesam:artist"), Value::from(vec!["Artist"]));
+        assert_eq!(field("xesam:trackNumber"), Value::from(3i32));
+        assert_eq!(
+            field("xesam:url"),
+            Value::from("file:///music/A%20Song.mp3")
+        );
+        assert!(!metadata.contains_key("xesam:album"));
+
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            player.set_property("Volume", value).unwrap();
+            player.set_property("Rate", value).unwrap();
+        }
+        player.set_property("Rate", 0.0).unwrap();
+        assert_eq!(player.get_property::<f64>("Volume").unwrap(), 0.8);
+        assert_eq!(player.get_property::<f64>("Rate").unwrap(), 1.0);
+        assert_eq!(mpris.try_command(), None);
+
+        player.set_property("Volume", 1.5).unwrap();
+        assert_eq!(mpris.try_command(), Some(MprisCommand::SetVolume(1.0)));
+        assert_eq!(player.get_property::<f64>("Volume").unwrap(), 1.0);
+        player.set_property("Rate", 100.0).unwrap();
+        let max = f64::from(MAX_SPEED);
+        assert_eq!(mpris.try_command(), Some(MprisCommand::SetRate(max)));
+        assert_eq!(player.get_property::<f64>("Rate").unwrap(), max);
+
+        let mut seeked = player.receive_signal("Seeked").unwrap();
+        let (sender, positions) = mpsc::channel();
+        thread::spawn(move || {
+            if let Some(message) = seeked.next() {
+                let _ = sender.send(message.body().deserialize::<i64>().unwrap());
+            }
+        });
+        player.call_method("Seek", &(-4_000_000i64,)).unwrap();
+        assert_eq!(mpris.try_command(), Some(MprisCommand::Seek(-4_000_000)));
+        let mut status = Status::lock(&mpris.status).clone();
+        status.position = 6_000_000;
+        mpris.publish(status, false, true);
+        assert_eq!(
+            positions.recv_timeout(Duration::from_secs(5)),
+            Ok(6_000_000)
+        );
+        assert_eq!(player.get_property::<i64>("Position").unwrap(), 6_000_000);
+        // Metadata is kept when it has not changed.
+        assert_eq!(
+            player
+                .get_property::<HashMap<String, OwnedValue>>("Metadata")
+                .unwrap()
+                .len(),
+            6
+        );
+
+        let current = ObjectPath::try_from(track_id.as_str()).unwrap();
+        player
+            .call_method("SetPosition", &(current, 2_000_000i64))
+            .unwrap();
+        assert_eq!(
+            mpris.try_command(),
+            Some(MprisCommand::SetPosition(Duration::from_secs(2)))
+        );
+        let stale = ObjectPath::try_from(NO_TRACK).unwrap();
+        player
+            .call_method("SetPosition", &(stale, 2_000_000i64))
+            .unwrap();
+        assert_eq!(mpris.try_command(), None);
+    }
+}
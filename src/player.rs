@@ -1,12 +1,26 @@
+use std::f32::consts::FRAC_PI_2;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
 use std::time::Duration;
 
+use rodio::source::{Amplify, SkipDuration};
 use rodio::{Decoder, OutputStream, Sink, Source};
 
+use crate::chapters::{Chapter, chapter_at, read_chapters};
+use crate::cue::{Span, TrackSpan};
+use crate::eq::{EqControl, EqGains, Equalizer};
+use crate::gapless::{Cancellable, GaplessTrim};
+use crate::metadata::{GaplessInfo, PlaybackTags, TrackMetadata, read_tags};
+use crate::queue::{PlayQueue, QueuedTrack, RepeatMode};
+use crate::replaygain::{AppliedGain, ReplayGainSettings};
+use crate::timestretch::{MAX_SPEED, MIN_SPEED, SPEED_STEP, SpeedControl, TimeStretch};
+use crate::visualizer::{SampleRing, SampleTap, SpectrumAnalyzer};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlaybackState {
     Playing,
@@ -16,41 +30,533 @@ pub enum PlaybackState {
 
 pub struct MusicPlayer {
     pub current_song_path: Option<PathBuf>,
+    /// Set when the current track is one of several in its file.
+    pub current_span: Option<TrackSpan>,
     pub current_song_name: Option<String>,
+    pub current_metadata: Option<TrackMetadata>,
     pub state: PlaybackState,
+    /// Chapters marked in the current file; never set for CUE sheet tracks.
+    chapters: Vec<Chapter>,
+    queue: PlayQueue,
     current_duration: Option<Duration>,
-    duration_rx: Option<Receiver<DurationUpdate>>,
-    _stream: OutputStream,
+    current_gain: Option<AppliedGain>,
+    current_gapless: Option<GaplessInfo>,
+    replay_gain: ReplayGainSettings,
+    /// Where in the track the sink clock last read `wall_base`. The sink
+    /// counts time played out, which runs apart from the track's own time
+    /// whenever the speed is not 1.
+    media_base: Duration,
+    wall_base: Duration,
+    speed: f32,
+    volume: f32,
+    muted: bool,
+    probe_tx: Sender<ProbeUpdate>,
+    probe_rx: Receiver<ProbeUpdate>,
+    /// The upcoming track whose tags are being read, so it is asked for once.
+    upcoming_probe: Option<(PathBuf, Option<TrackSpan>)>,
+    preload: Option<Preload>,
+    preload_blocked: bool,
+    crossfade: Duration,
+    fade: Option<Crossfade>,
+    same_album_next: Option<(PathBuf, Option<TrackSpan>, bool)>,
+    eq: Arc<EqControl>,
+    speed_control: Arc<SpeedControl>,
+    samples: Arc<SampleRing>,
+    spectrum: SpectrumAnalyzer,
+    stream: OutputStream,
     sink: Sink,
 }
 
-struct DurationUpdate {
+const VOLUME_STEP: f32 = 0.05;
+const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+/// How long before the end of a track the next one is appended to the sink.
+const PRELOAD_AHEAD: Duration = Duration::from_secs(10);
+
+type TrackSource =
+    Equalizer<TimeStretch<SkipDuration<Amplify<Span<GaplessTrim<Decoder<BufReader<File>>>>>>>>;
+
+/// The next track, already appended to the sink behind the current one.
+struct Preload {
+    index: usize,
+    track: QueuedTrack,
+    duration: Option<Duration>,
+    gain: Option<AppliedGain>,
+    cancel: Arc<AtomicBool>,
+}
+
+/// The previous track fading out on its own sink while `sink` fades in.
+struct Crossfade {
+    outgoing: Sink,
+    length: Duration,
+}
+
+/// What is read about a track off the UI thread: the current one, or the
+/// upcoming one when its tags are needed. Tags and chapters come first;
+/// probing the duration can take much longer.
+struct ProbeUpdate {
     path: PathBuf,
+    span: Option<TrackSpan>,
     duration: Option<Duration>,
+    tags: Option<(TrackMetadata, PlaybackTags)>,
+    chapters: Option<Vec<Chapter>>,
+}
+
+impl ProbeUpdate {
+    fn tags(path: PathBuf, span: Option<TrackSpan>) -> Self {
+        Self {
+            tags: Some(read_tags(&path).unwrap_or_default()),
+            path,
+            span,
+            duration: None,
+            chapters: None,
+        }
+    }
 }
 
 impl MusicPlayer {
     pub fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let stream = rodio::OutputStreamBuilder::open_default_stream()?;
-        let sink = rodio::Sink::connect_new(&stream.mixer());
+        let sink = rodio::Sink::connect_new(stream.mixer());
+        let (probe_tx, probe_rx) = mpsc::channel();
 
         Ok(Self {
             current_song_path: None,
+            current_span: None,
             current_song_name: None,
+            current_metadata: None,
             state: PlaybackState::Stopped,
+            chapters: Vec::new(),
+            queue: PlayQueue::default(),
             current_duration: None,
-            duration_rx: None,
-            _stream: stream,
+            current_gain: None,
+            current_gapless: None,
+            replay_gain: ReplayGainSettings::default(),
+            media_base: Duration::ZERO,
+            wall_base: Duration::ZERO,
+            speed: 1.0,
+            volume: 1.0,
+            muted: false,
+            probe_tx,
+            probe_rx,
+            upcoming_probe: None,
+            preload: None,
+            preload_blocked: false,
+            crossfade: Duration::ZERO,
+            fade: None,
+            same_album_next: None,
+            eq: EqControl::new(),
+            speed_control: SpeedControl::new(),
+            samples: SampleRing::new(),
+            spectrum: SpectrumAnalyzer::new(),
+            stream,
             sink,
         })
     }
 
-    pub fn update_state(&mut self) {
-        if let Some(rx) = &self.duration_rx {
-            if let Ok(update) = rx.try_recv() {
-                self.duration_rx = None;
-                if self.current_song_path.as_ref() == Some(&update.path) {
-                    self.current_duration = update.duration;
+    pub fn update_state(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let updates: Vec<ProbeUpdate> = self.probe_rx.try_iter().collect();
+        for update in updates {
+            if let Some((metadata, playback)) = &update.tags {
+                self.queue
+                    .fill_tags(&update.path, update.span, metadata, *playback);
+                let key = (update.path.clone(), update.span);
+                if self.upcoming_probe.as_ref() == Some(&key) {
+                    self.upcoming_probe = None;
+                }
+            }
+            if self.current_song_path.as_ref() != Some(&update.path)
+                || self.current_span != update.span
+            {
+                continue;
+            }
+            if update.duration.is_some() {
+                self.current_duration = update.duration;
+            }
+            if let Some(chapters) = update.chapters {
+                self.chapters = chapters;
+            }
+        }
+
+        self.spectrum
+            .update(&self.samples, self.state == PlaybackState::Playing);
+
+        if self.state == PlaybackState::Stopped {
+            return Ok(());
+        }
+        self.update_fade();
+        if self.preload.is_some() && self.sink.len() <= 1 {
+            return self.take_over_preload();
+        }
+        if self.sink.empty() {
+            return self.advance(true);
+        }
+        // A crossfade started while paused would be heard on its own sink.
+        if self.state == PlaybackState::Playing {
+            self.prepare_next();
+        }
+
+        Ok(())
+    }
+
+    /// Gets the upcoming track ready shortly before the current one ends:
+    /// crossfaded into when that is configured and it is from another album,
+    /// otherwise preloaded for a gapless join. Files that fail to open are
+    /// left to `advance`, which skips them.
+    fn prepare_next(&mut self) {
+        if self.fade.is_some()
+            || self.preload.is_some()
+            || self.preload_blocked
+            || self.sink.len() != 1
+        {
+            return;
+        }
+        let (Some(position), Some(total)) = (self.current_position(), self.current_duration) else {
+            return;
+        };
+        // In wall-clock time, which is what the sink and crossfade run on.
+        let remaining = total.saturating_sub(position).div_f32(self.speed);
+        if remaining > PRELOAD_AHEAD.max(self.crossfade) {
+            return;
+        }
+        let Some(index) = self.queue.peek_next(true) else {
+            return;
+        };
+        let Some(track) = self.queue.tracks().get(index).cloned() else {
+            return;
+        };
+        // Its gain, gapless trim and album are known once the probe thread
+        // has read its tags; until then this waits.
+        if track.playback.is_none() {
+            self.probe_upcoming();
+            return;
+        }
+
+        if !self.crossfade.is_zero() && !self.is_same_album(&track) {
+            if remaining <= self.crossfade {
+                self.start_crossfade(index, track, remaining);
+            }
+            return;
+        }
+        if remaining <= PRELOAD_AHEAD {
+            self.preload(index, track);
+        }
+    }
+
+    /// Whether `next` continues the album that is playing, in which case the
+    /// tracks are joined gaplessly rather than crossfaded. Cached per path,
+    /// since it is asked on every tick near the end of a track.
+    fn is_same_album(&mut self, next: &QueuedTrack) -> bool {
+        if let Some((path, span, same)) = &self.same_album_next
+            && *path == next.path
+            && *span == next.span
+        {
+            return *same;
+        }
+
+        let current = self
+            .current_metadata
+            .as_ref()
+            .and_then(|metadata| metadata.album.as_deref());
+        let next_album = next
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.album.as_deref());
+        let same = current.is_some() && current == next_album;
+        self.same_album_next = Some((next.path.clone(), next.span, same));
+        same
+    }
+
+    /// The upcoming track, when its tags have not been read and nobody is
+    /// reading them yet. It is marked as being read.
+    fn take_upcoming_probe(&mut self) -> Option<(PathBuf, Option<TrackSpan>)> {
+        let index = self.queue.peek_next(true)?;
+        let track = self.queue.tracks().get(index)?;
+        let key = (track.path.clone(), track.span);
+        if track.playback.is_some() || self.upcoming_probe.as_ref() == Some(&key) {
+            return None;
+        }
+        self.upcoming_probe = Some(key.clone());
+        Some(key)
+    }
+
+    /// Reads the tags of the upcoming track in the background. They reach
+    /// the queue through `update_state`.
+    fn probe_upcoming(&mut self) {
+        let Some((path, span)) = self.take_upcoming_probe() else {
+            return;
+        };
+        let tx = self.probe_tx.clone();
+        thread::spawn(move || {
+            let _ = tx.send(ProbeUpdate::tags(path, span));
+        });
+    }
+
+    /// Appends the upcoming track to the sink behind the current one, so the
+    /// sink moves on to it without a gap.
+    fn preload(&mut self, index: usize, track: QueuedTrack) {
+        let Ok((source, gain)) = self.open_with_gain(&track) else {
+            return;
+        };
+
+        let duration = source.total_duration().or(track.duration);
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.sink.append(SampleTap::new(
+            Cancellable::new(source, cancel.clone()),
+            self.samples.clone(),
+        ));
+        self.preload = Some(Preload {
+            index,
+            track,
+            duration,
+            gain,
+            cancel,
+        });
+    }
+
+    /// Starts the next track on a sink of its own, silent at first, and hands
+    /// the current one over to fade out for the rest of its `remaining` time.
+    fn start_crossfade(&mut self, index: usize, track: QueuedTrack, remaining: Duration) {
+        let Ok((source, gain)) = self.open_with_gain(&track) else {
+            return;
+        };
+        if self.queue.advance(true) != Some(index) {
+            return;
+        }
+
+        let duration = source.total_duration().or(track.duration);
+        let sink = Sink::connect_new(self.stream.mixer());
+        sink.set_volume(0.0);
+        sink.append(SampleTap::new(source, self.samples.clone()));
+        let outgoing = std::mem::replace(&mut self.sink, sink);
+        self.fade = Some(Crossfade {
+            outgoing,
+            length: remaining.min(self.crossfade),
+        });
+        self.set_position_base(Duration::ZERO, Duration::ZERO);
+        self.set_current_track(track, duration, gain);
+    }
+
+    /// Moves both volumes along an equal-power curve. Progress follows the
+    /// incoming track's position, so pausing holds the fade where it is.
+    fn update_fade(&mut self) {
+        let Some(fade) = &self.fade else {
+            return;
+        };
+        let progress = self.sink.get_pos().as_secs_f32() / fade.length.as_secs_f32().max(0.001);
+        if progress >= 1.0 || fade.outgoing.empty() {
+            self.finish_fade();
+            return;
+        }
+        let volume = self.effective_volume();
+        let angle = progress * FRAC_PI_2;
+        fade.outgoing.set_volume(volume * angle.cos());
+        self.sink.set_volume(volume * angle.sin());
+    }
+
+    /// Stops whatever is left of the outgoing track and restores the volume.
+    fn finish_fade(&mut self) {
+        if let Some(fade) = self.fade.take() {
+            fade.outgoing.stop();
+            self.apply_volume();
+        }
+    }
+
+    /// Called once the current track has ended and the preloaded one is
+    /// playing: moves the queue along and takes over the new track's details.
+    fn take_over_preload(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(preload) = self.preload.take() else {
+            return Ok(());
+        };
+        match self.queue.advance(true) {
+            Some(index) if index == preload.index => {
+                self.set_position_base(Duration::ZERO, Duration::ZERO);
+                self.set_current_track(preload.track, preload.duration, preload.gain);
+                Ok(())
+            }
+            Some(index) => self.play_index(index),
+            None => {
+                self.stop();
+                Ok(())
+            }
+        }
+    }
+
+    /// Drops the preloaded track when queue edits mean something else should
+    /// play next. It stays in the sink but ends as soon as it is reached.
+    fn revalidate_preload(&mut self) {
+        let Some(preload) = &mut self.preload else {
+            return;
+        };
+        let next = self.queue.peek_next(true);
+        let next_path = next.and_then(|index| self.queue.tracks().get(index));
+        match (next, next_path) {
+            (Some(index), Some(track))
+                if track.path == preload.track.path && track.span == preload.track.span =>
+            {
+                preload.index = index;
+            }
+            _ => {
+                preload.cancel.store(true, Ordering::Relaxed);
+                self.preload = None;
+                self.preload_blocked = true;
+            }
+        }
+    }
+
+    /// Forgets the preloaded track after the sink has been stopped.
+    fn reset_preload(&mut self) {
+        self.preload = None;
+        self.preload_blocked = false;
+    }
+
+    /// Takes effect from the next track that is opened.
+    pub fn set_replay_gain(&mut self, settings: ReplayGainSettings) {
+        self.replay_gain = settings;
+    }
+
+    /// The ReplayGain adjustment applied to the current track, if any.
+    pub fn current_gain(&self) -> Option<AppliedGain> {
+        self.current_gain
+    }
+
+    /// Applies new equalizer gains to what is playing and what follows.
+    pub fn set_equalizer(&self, gains: &EqGains) {
+        self.eq.set_gains(gains);
+    }
+
+    /// Sets how long tracks from different albums overlap. Zero turns
+    /// crossfading off.
+    pub fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade;
+    }
+
+    /// Replaces the whole queue with `tracks` and starts playing `start`.
+    pub fn play_queue(
+        &mut self,
+        tracks: Vec<QueuedTrack>,
+        start: usize,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.queue.replace(tracks, start);
+        let Some(index) = self.queue.current_index() else {
+            self.stop();
+            return Ok(());
+        };
+
+        match self.play_index(index) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let _ = self.advance(false);
+                Err(err)
+            }
+        }
+    }
+
+    /// Like [`Self::play_queue`], but leaves `start` paused at `position`.
+    pub fn resume_queue(
+        &mut self,
+        tracks: Vec<QueuedTrack>,
+        start: usize,
+        position: Duration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.queue.replace(tracks, start);
+        let Some(index) = self.queue.current_index() else {
+            self.stop();
+            return Ok(());
+        };
+
+        if let Err(err) = self.start_index(index, true) {
+            self.stop();
+            return Err(err);
+        }
+        if !position.is_zero() {
+            self.seek_to(position)?;
+        }
+        Ok(())
+    }
+
+    pub fn enqueue(&mut self, track: QueuedTrack) {
+        self.queue.push(track);
+        self.revalidate_preload();
+    }
+
+    pub fn enqueue_next(&mut self, track: QueuedTrack) {
+        self.queue.insert_next(track);
+        self.revalidate_preload();
+    }
+
+    /// Removes the queue entry at `index`. Removing the current track does not
+    /// interrupt it; the queue simply continues with the following entry.
+    pub fn remove_from_queue(&mut self, index: usize) -> Option<QueuedTrack> {
+        let removed = self.queue.remove(index);
+        self.revalidate_preload();
+        removed
+    }
+
+    /// Index of the first upcoming queue entry for `path` and `span`, if any.
+    pub fn upcoming_queue_position(&self, path: &Path, span: Option<TrackSpan>) -> Option<usize> {
+        self.queue.upcoming_index_of(path, span)
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+        self.revalidate_preload();
+    }
+
+    pub fn queue(&self) -> &PlayQueue {
+        &self.queue
+    }
+
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.queue.set_shuffle(shuffle);
+        self.revalidate_preload();
+    }
+
+    pub fn toggle_shuffle(&mut self) {
+        self.set_shuffle(!self.queue.shuffle());
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.queue.set_repeat(repeat);
+        self.revalidate_preload();
+    }
+
+    pub fn cycle_repeat(&mut self) {
+        self.set_repeat(self.queue.repeat().cycle());
+    }
+
+    pub fn next(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.advance(false)
+    }
+
+    /// Restarts the current track when it has been playing for a while,
+    /// otherwise goes back to the previous queue entry.
+    pub fn previous(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let played = self.current_position().unwrap_or(Duration::ZERO);
+        if played > RESTART_THRESHOLD
+            && let Some(current) = self.queue.current_index()
+        {
+            return self.play_index(current);
+        }
+        match self.queue.retreat() {
+            Some(index) => self.play_index(index),
+            None => Ok(()),
+        }
+    }
+
+    /// Plays the next queue entry, skipping over files that fail to open. The
+    /// first failure is still reported so the caller can surface it.
+    fn advance(&mut self, auto: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut auto = auto;
+        let mut first_error = None;
+        for _ in 0..self.queue.len() {
+            let Some(index) = self.queue.advance(auto) else {
+                break;
+            };
+            match self.play_index(index) {
+                Ok(()) => break,
+                Err(err) => {
+                    auto = false;
+                    first_error.get_or_insert(err);
                 }
             }
         }
@@ -58,50 +564,138 @@ impl MusicPlayer {
         if self.state != PlaybackState::Stopped && self.sink.empty() {
             self.clear_track_state();
         }
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn play_index(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.start_index(index, false)
     }
 
-    pub fn play_file(
+    fn start_index(
         &mut self,
-        path: PathBuf,
-        prefetched_duration: Option<Duration>,
+        index: usize,
+        paused: bool,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let file = File::open(&path)?;
-        let source = Decoder::new(BufReader::new(file))?;
+        let Some(mut track) = self.queue.tracks().get(index).cloned() else {
+            return Ok(());
+        };
+        // A track picked by hand has usually not been read ahead.
+        if track.playback.is_none() {
+            let (metadata, playback) = read_tags(&track.path).unwrap_or_default();
+            self.queue
+                .fill_tags(&track.path, track.span, &metadata, playback);
+            track.metadata.get_or_insert(metadata);
+            track.playback = Some(playback);
+        }
 
-        self.current_duration = source.total_duration().or(prefetched_duration);
-        self.duration_rx = None;
+        let (source, gain) = self.open_with_gain(&track)?;
+        let duration = source.total_duration().or(track.duration);
+        self.set_position_base(Duration::ZERO, Duration::ZERO);
+        self.finish_fade();
         self.sink.stop();
-        self.sink.append(source);
-        self.sink.play();
+        self.reset_preload();
+        // Paused before appending, so not a sample is heard.
+        if paused {
+            self.sink.pause();
+        } else {
+            self.sink.play();
+        }
+        self.sink
+            .append(SampleTap::new(source, self.samples.clone()));
+        self.state = if paused {
+            PlaybackState::Paused
+        } else {
+            PlaybackState::Playing
+        };
+        self.set_current_track(track, duration, gain);
+        Ok(())
+    }
+
+    /// Updates what is shown as playing. The audio itself is already in the
+    /// sink, either just appended or preloaded.
+    fn set_current_track(
+        &mut self,
+        track: QueuedTrack,
+        duration: Option<Duration>,
+        gain: Option<AppliedGain>,
+    ) {
+        self.current_duration = duration;
+        self.current_gain = gain;
+        self.current_gapless = track.playback.and_then(|playback| playback.gapless);
 
+        let path = track.path;
+        let span = track.span;
+        self.current_span = span;
         self.current_song_path = Some(path.clone());
-        self.current_song_name = Some(
-            path.file_stem()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string(),
-        );
-        self.state = PlaybackState::Playing;
+        self.chapters.clear();
+        self.set_metadata(&path, track.metadata);
 
-        if self.current_duration.is_none() {
-            let (tx, rx) = mpsc::channel();
-            let duration_path = path.clone();
+        // Chapters, a duration the container does not declare and the tags
+        // of the track after this one are read in the background, so
+        // changing tracks never waits on the disk.
+        let probe_duration_too = self.current_duration.is_none();
+        let read_chapters_too = span.is_none();
+        let upcoming = self.take_upcoming_probe();
+        if probe_duration_too || read_chapters_too || upcoming.is_some() {
+            let tx = self.probe_tx.clone();
             thread::spawn(move || {
-                let duration = probe_duration(&duration_path);
-                let _ = tx.send(DurationUpdate {
-                    path: duration_path,
-                    duration,
-                });
+                if read_chapters_too {
+                    let _ = tx.send(ProbeUpdate {
+                        path: path.clone(),
+                        span,
+                        duration: None,
+                        tags: None,
+                        chapters: Some(read_chapters(&path)),
+                    });
+                }
+                if let Some((path, span)) = upcoming {
+                    let _ = tx.send(ProbeUpdate::tags(path, span));
+                }
+                if probe_duration_too {
+                    let duration = probe_duration(&path);
+                    let _ = tx.send(ProbeUpdate {
+                        path,
+                        span,
+                        duration: match span {
+                            Some(span) => span.length(duration),
+                            None => duration,
+                        },
+                        tags: None,
+                        chapters: None,
+                    });
+                }
             });
-            self.duration_rx = Some(rx);
         }
+    }
 
-        Ok(())
+    /// Shows `metadata` for the current track, falling back to the file name
+    /// when it has no title.
+    fn set_metadata(&mut self, path: &Path, metadata: Option<TrackMetadata>) {
+        let metadata = metadata.filter(|metadata| !metadata.is_empty());
+        self.current_song_name = Some(
+            metadata
+                .as_ref()
+                .and_then(TrackMetadata::display_title)
+                .unwrap_or_else(|| {
+                    path.file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                }),
+        );
+        self.current_metadata = metadata;
     }
 
     pub fn pause(&mut self) {
         if self.state == PlaybackState::Playing {
             self.sink.pause();
+            if let Some(fade) = &self.fade {
+                fade.outgoing.pause();
+            }
             self.state = PlaybackState::Paused;
         }
     }
@@ -109,6 +703,9 @@ impl MusicPlayer {
     pub fn resume(&mut self) {
         if self.state == PlaybackState::Paused {
             self.sink.play();
+            if let Some(fade) = &self.fade {
+                fade.outgoing.play();
+            }
             self.state = PlaybackState::Playing;
         }
     }
@@ -122,31 +719,293 @@ impl MusicPlayer {
     }
 
     pub fn stop(&mut self) {
+        self.finish_fade();
         self.sink.stop();
+        self.reset_preload();
         self.clear_track_state();
     }
 
-    pub fn is_playing_track(&self, path: &Path) -> bool {
-        self.current_song_path.as_ref().map(PathBuf::as_path) == Some(path)
+    /// Moves the playback position by `seconds`, which may be negative.
+    pub fn seek_relative(
+        &mut self,
+        seconds: i64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(position) = self.current_position() else {
+            return Ok(());
+        };
+
+        let offset = Duration::from_secs(seconds.unsigned_abs());
+        let target = if seconds < 0 {
+            position.saturating_sub(offset)
+        } else {
+            position + offset
+        };
+        self.seek_to(target)
+    }
+
+    /// Jumps to `fraction` (0.0 to 1.0) of the current track.
+    pub fn seek_to_fraction(
+        &mut self,
+        fraction: f64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.state == PlaybackState::Stopped {
+            return Ok(());
+        }
+        let Some(total) = self.current_duration else {
+            return Err("cannot seek: track duration is unknown".into());
+        };
+        self.seek_to(total.mul_f64(fraction.clamp(0.0, 1.0)))
+    }
+
+    /// Seeks within the current track. Decoders that cannot seek fall back to
+    /// reopening the file and skipping forward to `target`.
+    pub fn seek_to(
+        &mut self,
+        target: Duration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(path) = self.current_song_path.clone() else {
+            return Ok(());
+        };
+        let target = match self.current_duration {
+            Some(total) => target.min(total),
+            None => target,
+        };
+
+        self.finish_fade();
+        // The sink clock restarts from `target` after a seek.
+        if self.sink.try_seek(target).is_ok() {
+            self.set_position_base(target, target);
+            return Ok(());
+        }
+
+        let source = self
+            .open_track(
+                &path,
+                self.current_span,
+                self.current_gain,
+                self.current_gapless,
+                target,
+            )
+            .map_err(|err| format!("seek failed: {err}"))?;
+        self.sink.stop();
+        self.reset_preload();
+        self.sink
+            .append(SampleTap::new(source, self.samples.clone()));
+        self.set_position_base(target, Duration::ZERO);
+        Ok(())
+    }
+
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    /// Index of the chapter playing now.
+    pub fn current_chapter(&self) -> Option<usize> {
+        chapter_at(&self.chapters, self.current_position()?)
+    }
+
+    pub fn next_chapter(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.chapters.is_empty() {
+            return Err("no chapters in this track".into());
+        }
+        let next = self.current_chapter().map_or(0, |index| index + 1);
+        match self.chapters.get(next) {
+            Some(chapter) => self.seek_to(chapter.start),
+            None => Err("already in the last chapter".into()),
+        }
+    }
+
+    /// Goes back to the start of the current chapter when it has been
+    /// playing for a while, otherwise to the previous chapter.
+    pub fn previous_chapter(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.chapters.is_empty() {
+            return Err("no chapters in this track".into());
+        }
+        let position = self.current_position().unwrap_or(Duration::ZERO);
+        let target = match chapter_at(&self.chapters, position) {
+            Some(index) if position - self.chapters[index].start > RESTART_THRESHOLD => {
+                self.chapters[index].start
+            }
+            Some(index) if index > 0 => self.chapters[index - 1].start,
+            _ => Duration::ZERO,
+        };
+        self.seek_to(target)
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Ignores a volume that is not a number, keeping the current one.
+    pub fn set_volume(&mut self, volume: f32) {
+        if !volume.is_finite() {
+            return;
+        }
+        self.volume = volume.clamp(0.0, 1.0);
+        self.muted = false;
+        self.apply_volume();
+    }
+
+    pub fn volume_up(&mut self) {
+        self.set_volume(self.volume + VOLUME_STEP);
+    }
+
+    pub fn volume_down(&mut self) {
+        self.set_volume(self.volume - VOLUME_STEP);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        self.apply_volume();
+    }
+
+    fn effective_volume(&self) -> f32 {
+        if self.muted { 0.0 } else { self.volume }
+    }
+
+    /// Applies the volume to the sink. During a crossfade `update_fade`
+    /// scales it on the next tick instead.
+    fn apply_volume(&self) {
+        if self.fade.is_none() {
+            self.sink.set_volume(self.effective_volume());
+        }
+    }
+
+    pub fn is_playing_track(&self, path: &Path, span: Option<TrackSpan>) -> bool {
+        self.current_song_path.as_deref() == Some(path) && self.current_span == span
     }
 
     pub fn current_position(&self) -> Option<Duration> {
         if self.state == PlaybackState::Stopped {
             None
         } else {
-            Some(self.sink.get_pos())
+            let played = self.sink.get_pos().saturating_sub(self.wall_base);
+            Some(self.media_base + played.mul_f32(self.speed))
+        }
+    }
+
+    fn set_position_base(&mut self, media: Duration, wall: Duration) {
+        self.media_base = media;
+        self.wall_base = wall;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Changes the playback speed, rounded to steps of 0.1 within 0.5-3x.
+    pub fn set_speed(&mut self, speed: f32) {
+        if !speed.is_finite() {
+            return;
+        }
+        let speed = ((speed / SPEED_STEP).round() * SPEED_STEP).clamp(MIN_SPEED, MAX_SPEED);
+        if let Some(position) = self.current_position() {
+            let wall = self.sink.get_pos();
+            self.set_position_base(position, wall);
         }
+        self.speed = speed;
+        self.speed_control.set_speed(speed);
+    }
+
+    pub fn speed_up(&mut self) {
+        self.set_speed(self.speed + SPEED_STEP);
+    }
+
+    pub fn speed_down(&mut self) {
+        self.set_speed(self.speed - SPEED_STEP);
+    }
+
+    pub fn preserves_pitch(&self) -> bool {
+        self.speed_control.preserves_pitch()
+    }
+
+    pub fn set_preserve_pitch(&self, preserve: bool) {
+        self.speed_control.set_preserve_pitch(preserve);
+    }
+
+    pub fn spectrum(&self) -> &[f32] {
+        self.spectrum.bars()
     }
 
     pub fn current_duration(&self) -> Option<Duration> {
         self.current_duration
     }
 
+    /// Opens `track` at the gain its ReplayGain tags call for, or failing
+    /// those, its measured loudness.
+    fn open_with_gain(
+        &self,
+        track: &QueuedTrack,
+    ) -> Result<(TrackSource, Option<AppliedGain>), Box<dyn std::error::Error + Send + Sync>> {
+        let replay_gain = track.playback.and_then(|playback| playback.replay_gain);
+        let gain = match replay_gain {
+            Some(tags) => self.replay_gain.gain_for(&tags),
+            None => track
+                .loudness
+                .and_then(|loudness| self.replay_gain.gain_for_loudness(&loudness)),
+        };
+        Ok((
+            self.open_track(
+                &track.path,
+                track.span,
+                gain,
+                track.playback.and_then(|playback| playback.gapless),
+                Duration::ZERO,
+            )?,
+            gain,
+        ))
+    }
+
+    /// Opens `path` for playback from `start` with the encoder delay and
+    /// padding in `gapless` trimmed, `gain` applied and the speed and equalizer in the chain. With
+    /// a `span`, only that part is played and `start` counts from its start.
+    fn open_track(
+        &self,
+        path: &Path,
+        span: Option<TrackSpan>,
+        gain: Option<AppliedGain>,
+        gapless: Option<GaplessInfo>,
+        start: Duration,
+    ) -> Result<TrackSource, Box<dyn std::error::Error + Send + Sync>> {
+        let file = File::open(path)?;
+        let byte_len = file.metadata()?.len();
+        let mut builder = Decoder::builder()
+            .with_data(BufReader::new(file))
+            .with_byte_len(byte_len)
+            .with_gapless(true);
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            builder = builder.with_hint(ext);
+        }
+        let decoder = builder.build()?;
+
+        // The MP3 decoder already trims using the LAME header.
+        let is_mp3 = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"));
+        let gapless = gapless.filter(|_| !is_mp3);
+        let factor = gain.map_or(1.0, |gain| gain.factor());
+        let trimmed = Span::new(GaplessTrim::new(decoder, gapless), span)
+            .amplify(factor)
+            .skip_duration(start);
+        Ok(Equalizer::new(
+            TimeStretch::new(trimmed, self.speed_control.clone()),
+            self.eq.clone(),
+        ))
+    }
+
     fn clear_track_state(&mut self) {
         self.current_song_path = None;
+        self.current_span = None;
         self.current_song_name = None;
+        self.current_metadata = None;
+        self.chapters.clear();
         self.current_duration = None;
-        self.duration_rx = None;
+        self.current_gain = None;
+        self.current_gapless = None;
         self.state = PlaybackState::Stopped;
     }
 }
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            matches!(
+                ext.to_ascii_lowercase().as_str(),
+                "m3u" | "m3u8" | "pls" | "xspf"
+            )
+        })
+        .unwrap_or(false)
+}
+
+pub fn read_playlist(path: &Path) -> io::Result<Vec<PlaylistEntry>> {
+    let raw = fs::read(path)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let text = decode_text(&raw);
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    Ok(match extension.as_deref() {
+        Some("pls") => parse_pls(&text, base),
+        Some("xspf") => parse_xspf(&text, base),
+        _ => parse_m3u(&text, base),
+    })
+}
+
+/// Parses M3U/M3U8 text. Relative paths are resolved against `base`, and
+/// `#EXTINF` lines attach their title and duration to the following entry.
+fn parse_m3u(text: &str, base: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending_info: Option<(Option<Duration>, Option<String>)> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            pending_info = Some(parse_extinf(info));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (duration, title) = pending_info.take().unwrap_or_default();
+        entries.push(PlaylistEntry {
+            path: resolve_entry_path(line, base),
+            title,
+            duration,
+        });
+    }
+
+    entries
+}
+
+#[derive(Default)]
+struct PlsEntry {
+    file: Option<String>,
+    title: Option<String>,
+    length: Option<Duration>,
+}
+
+/// Parses the INI-style PLS format (`FileN=`, `TitleN=`, `LengthN=`).
+fn parse_pls(text: &str, base: &Path) -> Vec<PlaylistEntry> {
+    let mut numbered: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let (field, number) =
+            key.split_at(key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len()));
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+
+        let slot = numbered.entry(number).or_default();
+        match field {
+            "file" => slot.file = Some(value.to_string()),
+            "title" if !value.is_empty() => slot.title = Some(value.to_string()),
+            "length" => {
+                slot.length = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|seconds| *seconds >= 0.0)
+                    .map(Duration::from_secs_f64);
+            }
+            _ => {}
+        }
+    }
+
+    numbered
+        .into_values()
+        .filter_map(|entry| {
+            Some(PlaylistEntry {
+                path: resolve_entry_path(&entry.file?, base),
+                title: entry.title,
+                duration: entry.length,
+            })
+        })
+        .collect()
+}
+
+/// Extracts `<track>` elements from an XSPF document. Only the handful of
+/// elements we display are read, so a full XML parser is not needed.
+fn parse_xspf(text: &str, base: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("<track>").or_else(|| rest.find("<track ")) {
+        let after_start = &rest[start..];
+        let Some(end) = after_start.find("</track>") else {
+            break;
+        };
+        let track = &after_start[..end];
+        rest = &after_start[end + "</track>".len()..];
+
+        let Some(location) = element_text(track, "location") else {
+            continue;
+        };
+        let title = element_text(track, "title");
+        let title = match (element_text(track, "creator"), title) {
+            (Some(creator), Some(title)) => Some(format!("{creator} - {title}")),
+            (_, title) => title,
+        };
+        let duration = element_text(track, "duration")
+            .and_then(|millis| millis.parse::<u64>().ok())
+            .map(Duration::from_millis);
+
+        entries.push(PlaylistEntry {
+            path: resolve_entry_path(&location, base),
+            title,
+            duration,
+        });
+    }
+
+    entries
+}
+
+/// Text content of the first `<name>` child, with XML entities decoded.
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    let text = unescape_xml(xml[start..end].trim());
+    Some(text).filter(|text| !text.is_empty())
+}
+
+fn unescape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let after = &rest[amp..];
+        let Some(semi) = after.find(';') else {
+            break;
+        };
+        let entity = &after[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(ch) => {
+                out.push(ch);
+                rest = &after[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Writes an extended M3U8 playlist. Paths below the playlist's own folder are
+/// stored relative to it so the folder can be moved as a whole.
+pub fn write_m3u8(path: &Path, entries: &[PlaylistEntry]) -> io::Result<()> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut file = fs::File::create(path)?;
+    writeln!(file, "#EXTM3U")?;
+    for entry in entries {
+        let seconds = entry
+            .duration
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(-1);
+        let title = entry.title.clone().unwrap_or_else(|| {
+            entry
+                .path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        });
+        let stored_path = entry.path.strip_prefix(base).unwrap_or(&entry.path);
+        writeln!(file, "#EXTINF:{seconds},{title}")?;
+        writeln!(file, "{}", stored_path.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+/// `#EXTINF:<seconds>[ attributes],<title>`; a negative length means unknown.
+fn parse_extinf(info: &str) -> (Option<Duration>, Option<String>) {
+    let (head, title) = info.split_once(',').unwrap_or((info, ""));
+    let seconds = head
+        .split_whitespace()
+        .next()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|seconds| *seconds >= 0.0);
+    let title = Some(title.trim().to_string()).filter(|title| !title.is_empty());
+    (seconds.map(Duration::from_secs_f64), title)
+}
+
+/// Resolves a playlist location against `base`. `file://` URIs are
+/// percent-decoded; other URLs are kept as-is and will not resolve to a file.
+fn resolve_entry_path(location: &str, base: &Path) -> PathBuf {
+    if let Some(uri_path) = location.strip_prefix("file://") {
+        let uri_path = uri_path.strip_prefix("localhost").unwrap_or(uri_path);
+        return PathBuf::from(percent_decode(uri_path));
+    }
+    if location.contains("://") {
+        return PathBuf::from(location);
+    }
+
+    let location = if cfg!(unix) {
+        location.replace('\\', "/")
+    } else {
+        location.to_string()
+    };
+    let path = PathBuf::from(location);
+    if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%'
+            && let Some(hex) = text.get(index + 1..index + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            decoded.push(byte);
+            index += 3;
+            continue;
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Playlists are usually UTF-8, but plain `.m3u` files from older players are
+/// often Latin-1.
+fn decode_text(raw: &[u8]) -> String {
+    let raw = raw.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(raw);
+    match std::str::from_utf8(raw) {
+        Ok(text) => text.to_string(),
+        Err(_) => raw.iter().map(|&byte| char::from(byte)).collect(),
+    }
+}
@@ -0,0 +1,435 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+
+use crate::cue::TrackSpan;
+use crate::loudness::Loudness;
+use crate::metadata::{PlaybackTags, TrackMetadata};
+
+#[derive(Debug, Clone)]
+pub struct QueuedTrack {
+    pub path: PathBuf,
+    pub duration: Option<Duration>,
+    pub metadata: Option<TrackMetadata>,
+    /// Measured loudness, for files without ReplayGain tags.
+    pub loudness: Option<Loudness>,
+    /// The part of the file to play, for a track from a CUE sheet.
+    pub span: Option<TrackSpan>,
+    /// ReplayGain and gapless tags, once the player has read them.
+    pub playback: Option<PlaybackTags>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+impl RepeatMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+}
+
+/// Tracks waiting to be played plus the order they are played in. `order`
+/// holds indices into `tracks` and is the identity permutation unless shuffle
+/// is on, so toggling shuffle never touches `tracks` or the current song.
+#[derive(Debug, Default)]
+pub struct PlayQueue {
+    tracks: Vec<QueuedTrack>,
+    order: Vec<usize>,
+    position: Option<usize>,
+    /// Set once the current entry has been removed while it plays. `position`
+    /// then points at the entry that slid into its place, which plays next.
+    current_removed: bool,
+    shuffle: bool,
+    repeat: RepeatMode,
+}
+
+impl PlayQueue {
+    pub fn tracks(&self) -> &[QueuedTrack] {
+        &self.tracks
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Index into `tracks()` of the current track. `None` once it has been
+    /// removed from the queue.
+    pub fn current_index(&self) -> Option<usize> {
+        self.current_position().map(|position| self.order[position])
+    }
+
+    /// Position of the current track in play order.
+    pub fn current_position(&self) -> Option<usize> {
+        self.position.filter(|_| !self.current_removed)
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
+
+    /// Replaces every track and makes `start` the current one.
+    pub fn replace(&mut self, tracks: Vec<QueuedTrack>, start: usize) {
+        self.tracks = tracks;
+        self.current_removed = false;
+        if self.tracks.is_empty() {
+            self.order.clear();
+            self.position = None;
+            return;
+        }
+
+        let start = start.min(self.tracks.len() - 1);
+        if self.shuffle {
+            self.order = shuffled_around(self.tracks.len(), Some(start));
+            self.position = Some(0);
+        } else {
+            self.order = (0..self.tracks.len()).collect();
+            self.position = Some(start);
+        }
+    }
+
+    pub fn push(&mut self, track: QueuedTrack) {
+        let index = self.tracks.len();
+        self.tracks.push(track);
+        if self.shuffle {
+            let first_upcoming = self.next_position();
+            let at = rand::random::<usize>() % (self.order.len() - first_upcoming + 1);
+            self.order.insert(first_upcoming + at, index);
+        } else {
+            self.order.push(index);
+        }
+    }
+
+    /// Gives the entries for `path` the tags read from it. Those of a CUE
+    /// sheet track keep the metadata of their sheet.
+    pub fn fill_tags(
+        &mut self,
+        path: &Path,
+        span: Option<TrackSpan>,
+        metadata: &TrackMetadata,
+        playback: PlaybackTags,
+    ) {
+        for track in self.tracks.iter_mut().filter(|track| track.path == path) {
+            if track.metadata.is_none() && track.span == span {
+                track.metadata = Some(metadata.clone());
+            }
+            track.playback = Some(playback);
+        }
+    }
+
+    /// Queues `track` to play right after the current one.
+    pub fn insert_next(&mut self, track: QueuedTrack) {
+        let index = match self.current_index() {
+            Some(current) => current + 1,
+            None if self.current_removed => self
+                .order
+                .get(self.next_position())
+                .copied()
+                .unwrap_or(self.tracks.len()),
+            None => 0,
+        };
+        self.tracks.insert(index, track);
+        for entry in &mut self.order {
+            if *entry >= index {
+                *entry += 1;
+            }
+        }
+        self.order.insert(self.next_position(), index);
+    }
+
+    /// Removes `tracks()[index]`. Removing the current track does not stop it;
+    /// the queue simply continues with what would have followed it, even with
+    /// repeat-one.
+    pub fn remove(&mut self, index: usize) -> Option<QueuedTrack> {
+        if index >= self.tracks.len() {
+            return None;
+        }
+
+        let removed = self.tracks.remove(index);
+        let at = self.order.iter().position(|&entry| entry == index)?;
+        self.order.remove(at);
+        for entry in &mut self.order {
+            if *entry > index {
+                *entry -= 1;
+            }
+        }
+        match self.position {
+            Some(position) if at < position => self.position = Some(position - 1),
+            Some(position) if at == position && !self.current_removed => {
+                self.current_removed = true;
+            }
+            _ => {}
+        }
+        if self.order.is_empty() {
+            self.position = None;
+            self.current_removed = false;
+        }
+        Some(removed)
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.order.clear();
+        self.position = None;
+        self.current_removed = false;
+    }
+
+    /// Index of the first upcoming entry for `path` and `span`, if any.
+    pub fn upcoming_index_of(&self, path: &Path, span: Option<TrackSpan>) -> Option<usize> {
+        self.order[self.next_position()..]
+            .iter()
+            .copied()
+            .find(|&index| self.tracks[index].path == path && self.tracks[index].span == span)
+    }
+
+    /// Moves to the track that should play next and returns its index.
+    /// `auto` marks the end of a track, where repeat-one replays it instead.
+    pub fn advance(&mut self, auto: bool) -> Option<usize> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        if auto && self.repeat == RepeatMode::One && self.current_index().is_some() {
+            return self.current_index();
+        }
+
+        let next = self.next_position();
+        if next < self.order.len() {
+            self.position = Some(next);
+        } else if self.repeat == RepeatMode::All {
+            if self.shuffle {
+                let last = self.current_index();
+                self.order = shuffled_around(self.tracks.len(), None);
+                if self.order.len() > 1 && self.order.first().copied() == last {
+                    self.order.swap(0, 1);
+                }
+            }
+            self.position = Some(0);
+        } else {
+            return None;
+        }
+        self.current_removed = false;
+        self.current_index()
+    }
+
+    /// The index `advance(auto)` would move to, when that is already known.
+    /// A shuffled repeat-all wrap reshuffles first, so it is not.
+    pub fn peek_next(&self, auto: bool) -> Option<usize> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        if auto && self.repeat == RepeatMode::One && self.current_index().is_some() {
+            return self.current_index();
+        }
+
+        let next = self.next_position();
+        if next < self.order.len() {
+            Some(self.order[next])
+        } else if self.repeat == RepeatMode::All && !self.shuffle {
+            self.order.first().copied()
+        } else {
+            None
+        }
+    }
+
+    /// Steps back in play order, wrapping around with repeat-all. Stays on the
+    /// first track otherwise. After the current entry was removed, the one
+    /// before its place is the previous one.
+    pub fn retreat(&mut self) -> Option<usize> {
+        let position = self.position?;
+        self.current_removed = false;
+        if position > 0 {
+            self.position = Some(position - 1);
+        } else if self.repeat == RepeatMode::All {
+            self.position = Some(self.order.len() - 1);
+        }
+        self.current_index()
+    }
+
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if self.shuffle == shuffle {
+            return;
+        }
+        self.shuffle = shuffle;
+
+        let current = self.current_index();
+        // Where play continues when the current entry is gone.
+        let upcoming = self
+            .position
+            .filter(|_| self.current_removed)
+            .map(|position| {
+                self.order
+                    .get(position)
+                    .copied()
+                    .unwrap_or(self.tracks.len())
+            });
+        if shuffle {
+            self.order = shuffled_around(self.tracks.len(), current);
+            self.position = current.map(|_| 0);
+            self.current_removed = false;
+        } else {
+            self.order = (0..self.tracks.len()).collect();
+            self.position = current.or(upcoming);
+        }
+    }
+
+    fn next_position(&self) -> usize {
+        match self.position {
+            Some(position) if self.current_removed => position,
+            Some(position) => position + 1,
+            None => 0,
+        }
+    }
+}
+
+/// A random permutation of `0..len`, with `first` (if any) kept in front.
+fn shuffled_around(len: usize, first: Option<usize>) -> Vec<usize> {
+    let mut rest: Vec<usize> = (0..len).filter(|&index| Some(index) != first).collect();
+    rest.shuffle(&mut rand::thread_rng());
+    first.into_iter().chain(rest).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(name: &str) -> QueuedTrack {
+        QueuedTrack {
+            path: PathBuf::from(name),
+            duration: None,
+            metadata: None,
+            loudness: None,
+            span: None,
+            playback: None,
+        }
+    }
+
+    /// A queue of tracks named "0", "1", ... with `start` current.
+    fn queue(len: usize, start: usize) -> PlayQueue {
+        let mut queue = PlayQueue::default();
+        queue.replace((0..len).map(|n| track(&n.to_string())).collect(), start);
+        queue
+    }
+
+    fn name(queue: &PlayQueue, index: Option<usize>) -> Option<&str> {
+        let track = queue.tracks().get(index?)?;
+        track.path.to_str()
+    }
+
+    #[test]
+    fn removing_the_current_track_under_repeat_one_plays_the_next_once() {
+        let mut queue = queue(3, 1);
+        queue.set_repeat(RepeatMode::One);
+        queue.remove(1);
+        assert_eq!(queue.current_index(), None);
+        assert_eq!(name(&queue, queue.peek_next(true)), Some("2"));
+        let next = queue.advance(true);
+        assert_eq!(name(&queue, next), Some("2"));
+        // Repeat-one applies again to the track that took over.
+        let again = queue.advance(true);
+        assert_eq!(name(&queue, again), Some("2"));
+    }
+
+    #[test]
+    fn removing_the_current_track_under_repeat_all() {
+        let mut middle = queue(3, 1);
+        middle.set_repeat(RepeatMode::All);
+        middle.remove(1);
+        let next = middle.advance(true);
+        assert_eq!(name(&middle, next), Some("2"));
+
+        let mut last = queue(3, 2);
+        last.set_repeat(RepeatMode::All);
+        last.remove(2);
+        let next = last.advance(true);
+        assert_eq!(name(&last, next), Some("0"));
+    }
+
+    #[test]
+    fn removing_the_last_current_track_without_repeat_ends_the_queue() {
+        let mut queue = queue(3, 2);
+        queue.remove(2);
+        assert_eq!(queue.advance(true), None);
+    }
+
+    #[test]
+    fn insert_next_while_shuffled_plays_right_after_the_current_track() {
+        let mut queue = queue(6, 3);
+        queue.set_shuffle(true);
+        let current = name(&queue, queue.current_index()).map(str::to_string);
+        queue.insert_next(track("x"));
+        assert_eq!(
+            name(&queue, queue.current_index()).map(str::to_string),
+            current
+        );
+        let next = queue.advance(false);
+        assert_eq!(name(&queue, next), Some("x"));
+    }
+
+    #[test]
+    fn insert_next_after_removing_the_current_track() {
+        let mut queue = queue(3, 1);
+        queue.remove(1);
+        queue.insert_next(track("x"));
+        let next = queue.advance(false);
+        assert_eq!(name(&queue, next), Some("x"));
+        let next = queue.advance(false);
+        assert_eq!(name(&queue, next), Some("2"));
+    }
+
+    #[test]
+    fn toggling_shuffle_keeps_the_current_track() {
+        let mut queue = queue(8, 2);
+        queue.set_shuffle(true);
+        assert_eq!(name(&queue, queue.current_index()), Some("2"));
+        assert_eq!(queue.current_position(), Some(0));
+
+        queue.advance(false);
+        let current = queue.current_index().unwrap();
+        queue.set_shuffle(false);
+        assert_eq!(queue.current_index(), Some(current));
+        assert_eq!(queue.current_position(), Some(current));
+        let expected = (current + 1 < 8).then_some(current + 1);
+        assert_eq!(queue.advance(false), expected);
+    }
+
+    #[test]
+    fn shuffle_plays_every_track_once() {
+        let mut queue = queue(10, 4);
+        queue.set_shuffle(true);
+        let mut played = vec![queue.current_index().unwrap()];
+        while let Some(index) = queue.advance(true) {
+            played.push(index);
+        }
+        played.sort_unstable();
+        assert_eq!(played, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn retreat_at_the_first_track() {
+        let mut queue = queue(3, 0);
+        assert_eq!(queue.retreat(), Some(0));
+        queue.set_repeat(RepeatMode::All);
+        assert_eq!(queue.retreat(), Some(2));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::loudness::Loudness;
+use crate::metadata::ReplayGainTags;
+
+/// ReplayGain 2.0 reference level, used to turn measured loudness into gain.
+const REFERENCE_LUFS: f32 = -18.0;
+
+/// Which of the tagged gains to apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayGainMode {
+    Off,
+    #[default]
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    pub const NAMES: [&str; 3] = ["off", "track", "album"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "track" => Some(Self::Track),
+            "album" => Some(Self::Album),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    /// Added to the tagged gain, in dB.
+    pub preamp: f32,
+    /// Lowers the gain where the tagged peak would otherwise clip.
+    pub prevent_clipping: bool,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::default(),
+            preamp: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+/// Where an applied gain came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GainSource {
+    TrackTag,
+    AlbumTag,
+    /// Measured by the background loudness analysis of an untagged file.
+    Analyzed,
+}
+
+impl GainSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TrackTag => "track",
+            Self::AlbumTag => "album",
+            Self::Analyzed => "analyzed",
+        }
+    }
+}
+
+/// The gain applied to a track and where it came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AppliedGain {
+    pub db: f32,
+    /// For tags, the preferred one falls back to the other when only one is
+    /// present.
+    pub source: GainSource,
+    /// Whether clipping prevention lowered the gain.
+    pub limited: bool,
+}
+
+impl AppliedGain {
+    pub fn factor(&self) -> f32 {
+        10f32.powf(self.db / 20.0)
+    }
+}
+
+impl ReplayGainSettings {
+    pub fn gain_for(&self, tags: &ReplayGainTags) -> Option<AppliedGain> {
+        let track = tags
+            .track_gain
+            .map(|gain| (gain, tags.track_peak, GainSource::TrackTag));
+        let album = tags
+            .album_gain
+            .map(|gain| (gain, tags.album_peak, GainSource::AlbumTag));
+        let (gain, peak, source) = match self.mode {
+            ReplayGainMode::Off => return None,
+            ReplayGainMode::Track => track.or(album)?,
+            ReplayGainMode::Album => album.or(track)?,
+        };
+        Some(self.apply(gain, peak, source))
+    }
+
+    /// Gain for an untagged file from its measured loudness. Used in both
+    /// track and album mode, since only the track itself was measured.
+    pub fn gain_for_loudness(&self, loudness: &Loudness) -> Option<AppliedGain> {
+        if self.mode == ReplayGainMode::Off {
+            return None;
+        }
+        Some(self.apply(
+            REFERENCE_LUFS - loudness.integrated,
+            Some(loudness.true_peak),
+            GainSource::Analyzed,
+        ))
+    }
+
+    fn apply(&self, gain: f32, peak: Option<f32>, source: GainSource) -> AppliedGain {
+        let mut db = gain + self.preamp;
+        let mut limited = false;
+        if self.prevent_clipping
+            && let Some(peak) = peak.filter(|peak| *peak > 0.0)
+        {
+            let headroom = -20.0 * peak.log10();
+            if db > headroom {
+                db = headroom;
+                limited = true;
+            }
+        }
+        AppliedGain {
+            db,
+            source,
+            limited,
+        }
+    }
+}
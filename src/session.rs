@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cue::TrackSpan;
+use crate::metadata::{decode_number, decode_text, encode_number, encode_text, take_flag};
+
+const VERSION: u8 = 2;
+
+/// Where the player was when it last ran, so the next launch can pick up
+/// from there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub directory: PathBuf,
+    /// The playlist open as a virtual folder, if any.
+    pub playlist: Option<PathBuf>,
+    pub selected: Option<PathBuf>,
+    /// Each queued file, with the span played of it for a CUE sheet track.
+    pub queue: Vec<(PathBuf, Option<TrackSpan>)>,
+    /// Index into `queue` of the track that was playing or paused.
+    pub current: Option<usize>,
+    pub position: Duration,
+    pub paused: bool,
+}
+
+impl Session {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![VERSION];
+        encode_text(&mut bytes, Some(&path_text(&self.directory)));
+        encode_text(
+            &mut bytes,
+            self.playlist.as_deref().map(path_text).as_deref(),
+        );
+        encode_text(
+            &mut bytes,
+            self.selected.as_deref().map(path_text).as_deref(),
+        );
+        bytes.extend_from_slice(&(self.queue.len() as u32).to_le_bytes());
+        for (path, span) in &self.queue {
+            encode_text(&mut bytes, Some(&path_text(path)));
+            match span {
+                Some(span) => {
+                    bytes.push(1);
+                    encode_millis(&mut bytes, Some(span.start));
+                    encode_millis(&mut bytes, span.end);
+                }
+                None => bytes.push(0),
+            }
+        }
+        encode_number(&mut bytes, self.current.map(|index| index as u32));
+        encode_millis(&mut bytes, Some(self.position));
+        bytes.push(u8::from(self.paused));
+        bytes
+    }
+
+    pub fn decode(raw: &[u8]) -> Option<Self> {
+        let (&version, mut rest) = raw.split_first()?;
+        if version != VERSION {
+            return None;
+        }
+        let directory = PathBuf::from(decode_text(&mut rest)??);
+        let playlist = decode_text(&mut rest)?.map(PathBuf::from);
+        let selected = decode_text(&mut rest)?.map(PathBuf::from);
+        let (count, tail) = rest.split_first_chunk::<4>()?;
+        rest = tail;
+        let queue = (0..u32::from_le_bytes(*count))
+            .map(|_| {
+                let path = PathBuf::from(decode_text(&mut rest)??);
+                if !take_flag(&mut rest)? {
+                    return Some((path, None));
+                }
+                let start = decode_millis(&mut rest)??;
+                let end = decode_millis(&mut rest)?;
+                Some((path, Some(TrackSpan { start, end })))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let current = decode_number(&mut rest)?.map(|index| index as usize);
+        let position = decode_millis(&mut rest)??;
+        let paused = take_flag(&mut rest)?;
+        Some(Self {
+            directory,
+            playlist,
+            selected,
+            queue,
+            current,
+            position,
+            paused,
+        })
+    }
+}
+
+fn encode_millis(bytes: &mut Vec<u8>, duration: Option<Duration>) {
+    match duration {
+        Some(duration) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&(duration.as_millis() as u64).to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn decode_millis(raw: &mut &[u8]) -> Option<Option<Duration>> {
+    if !take_flag(raw)? {
+        return Some(None);
+    }
+    take_millis(raw).map(Some)
+}
+
+fn take_millis(raw: &mut &[u8]) -> Option<Duration> {
+    let (millis, rest) = raw.split_first_chunk::<8>()?;
+    *raw = rest;
+    Some(Duration::from_millis(u64::from_le_bytes(*millis)))
+}
+
+fn path_text(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
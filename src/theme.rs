@@ -0,0 +1,220 @@
+use std::str::FromStr;
+
+use ratatui::style::{Color, Modifier, Style};
+
+/// Colours for every styled element of the UI. `Color::Reset` leaves the
+/// terminal's own colour in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub name: String,
+    pub text: Color,
+    pub background: Color,
+    pub border: Color,
+    pub title: Color,
+    pub highlight_fg: Color,
+    pub highlight_bg: Color,
+    pub playing: Color,
+    pub filter_match: Color,
+    pub visualizer: Color,
+    pub progress: Color,
+    pub volume: Color,
+    pub muted: Color,
+    pub gauge_background: Color,
+}
+
+const PRESET_NAMES: [&str; 4] = ["dark", "light", "high-contrast", "monochrome"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            text: Color::Reset,
+            background: Color::Reset,
+            border: Color::Reset,
+            title: Color::Reset,
+            highlight_fg: Color::Black,
+            highlight_bg: Color::Yellow,
+            playing: Color::Yellow,
+            filter_match: Color::Cyan,
+            visualizer: Color::Cyan,
+            progress: Color::LightBlue,
+            volume: Color::Green,
+            muted: Color::DarkGray,
+            gauge_background: Color::DarkGray,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            text: Color::Rgb(0x2e, 0x34, 0x40),
+            background: Color::Rgb(0xf5, 0xf5, 0xf0),
+            border: Color::Rgb(0x9a, 0xa0, 0xa6),
+            title: Color::Rgb(0x1f, 0x4e, 0x8c),
+            highlight_fg: Color::Rgb(0xff, 0xff, 0xff),
+            highlight_bg: Color::Rgb(0x1f, 0x6f, 0xc5),
+            playing: Color::Rgb(0xb3, 0x5c, 0x00),
+            filter_match: Color::Rgb(0xc0, 0x1c, 0x5c),
+            visualizer: Color::Rgb(0x1f, 0x6f, 0xc5),
+            progress: Color::Rgb(0x1f, 0x6f, 0xc5),
+            volume: Color::Rgb(0x2e, 0x8b, 0x57),
+            muted: Color::Rgb(0x9a, 0xa0, 0xa6),
+            gauge_background: Color::Rgb(0xdc, 0xdc, 0xd6),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "high-contrast".to_string(),
+            text: Color::White,
+            background: Color::Black,
+            border: Color::White,
+            title: Color::LightYellow,
+            highlight_fg: Color::Black,
+            highlight_bg: Color::LightYellow,
+            playing: Color::LightGreen,
+            filter_match: Color::LightMagenta,
+            visualizer: Color::LightCyan,
+            progress: Color::LightCyan,
+            volume: Color::LightGreen,
+            muted: Color::Gray,
+            gauge_background: Color::Black,
+        }
+    }
+
+    pub fn monochrome() -> Self {
+        Self {
+            name: "monochrome".to_string(),
+            text: Color::Reset,
+            background: Color::Reset,
+            border: Color::Gray,
+            title: Color::White,
+            highlight_fg: Color::Black,
+            highlight_bg: Color::White,
+            playing: Color::White,
+            filter_match: Color::White,
+            visualizer: Color::Gray,
+            progress: Color::White,
+            volume: Color::Gray,
+            muted: Color::DarkGray,
+            gauge_background: Color::DarkGray,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    pub fn presets() -> Vec<Self> {
+        PRESET_NAMES.into_iter().filter_map(Self::preset).collect()
+    }
+
+    /// Builds a theme from the `[theme]` config table: an optional `preset`,
+    /// then per-element overrides given as colour names, indices or
+    /// `#rrggbb` hex.
+    pub fn from_config(table: &toml::Table, problems: &mut Vec<String>) -> Self {
+        let mut theme = match table.get("preset") {
+            None => Self::default(),
+            Some(toml::Value::String(name)) => Self::preset(name).unwrap_or_else(|| {
+                problems.push(format!(
+                    "theme.preset: unknown preset `{name}` (expected one of {})",
+                    PRESET_NAMES.join(", ")
+                ));
+                Self::default()
+            }),
+            Some(_) => {
+                problems.push("theme.preset: expected a string".to_string());
+                Self::default()
+            }
+        };
+
+        let mut customized = false;
+        for (key, value) in table {
+            if key == "preset" {
+                continue;
+            }
+            let Some(slot) = theme.color_mut(key) else {
+                problems.push(format!("theme.{key}: unknown theme element"));
+                continue;
+            };
+            let Some(text) = value.as_str() else {
+                problems.push(format!("theme.{key}: expected a colour string"));
+                continue;
+            };
+            match Color::from_str(text) {
+                Ok(color) => {
+                    *slot = color;
+                    customized = true;
+                }
+                Err(_) => problems.push(format!("theme.{key}: invalid colour `{text}`")),
+            }
+        }
+        if customized {
+            theme.name = format!("{} (custom)", theme.name);
+        }
+        theme
+    }
+
+    fn color_mut(&mut self, key: &str) -> Option<&mut Color> {
+        Some(match key {
+            "text" => &mut self.text,
+            "background" => &mut self.background,
+            "border" => &mut self.border,
+            "title" => &mut self.title,
+            "highlight-fg" => &mut self.highlight_fg,
+            "highlight-bg" => &mut self.highlight_bg,
+            "playing" => &mut self.playing,
+            "filter-match" => &mut self.filter_match,
+            "visualizer" => &mut self.visualizer,
+            "progress" => &mut self.progress,
+            "volume" => &mut self.volume,
+            "muted" => &mut self.muted,
+            "gauge-background" => &mut self.gauge_background,
+            _ => return None,
+        })
+    }
+
+    pub fn base(&self) -> Style {
+        Style::default().fg(self.text).bg(self.background)
+    }
+
+    pub fn border(&self) -> Style {
+        Style::default().fg(self.border)
+    }
+
+    pub fn title(&self) -> Style {
+        Style::default().fg(self.title)
+    }
+
+    pub fn highlight(&self) -> Style {
+        Style::default().fg(self.highlight_fg).bg(self.highlight_bg)
+    }
+
+    pub fn playing(&self) -> Style {
+        Style::default()
+            .fg(self.playing)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    pub fn filter_match(&self) -> Style {
+        Style::default()
+            .fg(self.filter_match)
+            .add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+    }
+
+    pub fn gauge(&self, color: Color) -> Style {
+        Style::default().fg(color).bg(self.gauge_background)
+    }
+}
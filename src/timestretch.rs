@@ -0,0 +1,395 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+pub const SPEED_STEP: f32 = 0.1;
+
+/// What a chosen playback speed is remembered for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpeedMemory {
+    Off,
+    File,
+    #[default]
+    Folder,
+}
+
+impl SpeedMemory {
+    pub const NAMES: [&str; 3] = ["off", "file", "folder"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "file" => Some(Self::File),
+            "folder" => Some(Self::Folder),
+            _ => None,
+        }
+    }
+}
+
+/// Playback speed shared between the player and every [`TimeStretch`] in the
+/// sink, so changes are heard straight away.
+#[derive(Debug)]
+pub struct SpeedControl {
+    speed: AtomicU32,
+    preserve_pitch: AtomicBool,
+}
+
+impl SpeedControl {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            speed: AtomicU32::new(1.0_f32.to_bits()),
+            preserve_pitch: AtomicBool::new(true),
+        })
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn set_preserve_pitch(&self, preserve: bool) {
+        self.preserve_pitch.store(preserve, Ordering::Relaxed);
+    }
+
+    fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    pub fn preserves_pitch(&self) -> bool {
+        self.preserve_pitch.load(Ordering::Relaxed)
+    }
+}
+
+/// Plays its source faster or slower. With pitch preservation on, it uses
+/// WSOLA: overlapping 40 ms windows are taken from the input at the sped-up
+/// rate, each shifted by up to 10 ms to line up with the previous one, and
+/// cross-faded together. Otherwise the input is simply resampled, which
+/// shifts the pitch along with the speed.
+pub struct TimeStretch<S> {
+    input: S,
+    control: Arc<SpeedControl>,
+    channels: usize,
+    sample_rate: SampleRate,
+    /// Frames produced per step; windows are twice this long.
+    hop: usize,
+    /// How far, in frames, a window may move to line up with the last one.
+    tolerance: usize,
+    window: Vec<f32>,
+    /// Decoded input, interleaved. `read_pos` is in frames from its start.
+    buffer: Vec<f32>,
+    read_pos: f64,
+    input_done: bool,
+    /// The first sample of an input span in a new format, held back until
+    /// the input buffered in the old one has been played.
+    held: Option<Sample>,
+    output: VecDeque<Sample>,
+    /// Faded-out second half of the last window, to be added to the next.
+    overlap: Vec<f32>,
+    /// Where the input following the last window starts, while in WSOLA.
+    continuation: Option<usize>,
+    /// Mono input following the last window, which the next one should
+    /// resemble for a seamless join.
+    natural: Vec<f32>,
+}
+
+/// Every `CORRELATION_STRIDE`th frame is compared when lining up windows.
+const CORRELATION_STRIDE: usize = 4;
+
+impl<S: Source> TimeStretch<S> {
+    pub fn new(input: S, control: Arc<SpeedControl>) -> Self {
+        let mut stretch = Self {
+            input,
+            control,
+            channels: 1,
+            sample_rate: 0,
+            hop: 0,
+            tolerance: 0,
+            window: Vec::new(),
+            buffer: Vec::new(),
+            read_pos: 0.0,
+            input_done: false,
+            held: None,
+            output: VecDeque::new(),
+            overlap: Vec::new(),
+            continuation: None,
+            natural: Vec::new(),
+        };
+        stretch.configure();
+        stretch
+    }
+
+    /// Takes on the input's current format and drops whatever was buffered
+    /// in the previous one.
+    fn configure(&mut self) {
+        self.channels = usize::from(self.input.channels().max(1));
+        self.sample_rate = self.input.sample_rate();
+        self.hop = (self.sample_rate as usize / 50).max(64);
+        self.tolerance = self.hop / 2;
+        self.window = (0..self.hop * 2)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (self.hop * 2) as f32).cos())
+            .collect();
+        self.buffer.clear();
+        self.read_pos = 0.0;
+        self.overlap.clear();
+        self.continuation = None;
+        self.natural.clear();
+    }
+
+    fn format_changed(&self) -> bool {
+        usize::from(self.input.channels().max(1)) != self.channels
+            || self.input.sample_rate() != self.sample_rate
+    }
+
+    fn frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// Reads input until `frames` frames are buffered, it runs out or its
+    /// format changes. Sources report a new format once the first sample of
+    /// the span it starts has been read, so that is checked between frames.
+    fn ensure(&mut self, frames: usize) {
+        while !self.input_done && self.held.is_none() && self.frames() < frames {
+            let Some(sample) = self.input.next() else {
+                self.input_done = true;
+                break;
+            };
+            if self.buffer.len().is_multiple_of(self.channels) && self.format_changed() {
+                self.held = Some(sample);
+                break;
+            }
+            self.buffer.push(sample);
+        }
+    }
+
+    fn sample(&self, frame: usize, channel: usize) -> f32 {
+        self.buffer[frame * self.channels + channel]
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        (0..self.channels)
+            .map(|channel| self.sample(frame, channel))
+            .sum::<f32>()
+            / self.channels as f32
+    }
+
+    /// Produces the next stretch of output, all of it in one format. Returns
+    /// false at the end.
+    fn fill(&mut self) -> bool {
+        if self.produce() {
+            return true;
+        }
+        let Some(sample) = self.held.take() else {
+            return false;
+        };
+        self.configure();
+        self.buffer.push(sample);
+        self.produce()
+    }
+
+    fn produce(&mut self) -> bool {
+        let speed = self.control.speed().clamp(MIN_SPEED, MAX_SPEED);
+        let normal = (speed - 1.0).abs() < 0.001;
+        let preserve_pitch = self.control.preserves_pitch();
+        if normal || !preserve_pitch {
+            self.leave_wsola();
+        }
+        let produced = if normal {
+            self.pass_through()
+        } else if preserve_pitch {
+            self.wsola_step(speed)
+        } else {
+            self.resample(speed)
+        };
+        self.compact();
+        produced
+    }
+
+    fn pass_through(&mut self) -> bool {
+        let start = self.read_pos as usize;
+        self.ensure(start + self.hop);
+        let end = self.frames().min(start + self.hop);
+        if end <= start {
+            return false;
+        }
+        self.output
+            .extend(&self.buffer[start * self.channels..end * self.channels]);
+        self.read_pos = end as f64;
+        true
+    }
+
+    fn resample(&mut self, speed: f32) -> bool {
+        let mut produced = false;
+        for _ in 0..self.hop {
+            let frame = self.read_pos as usize;
+            self.ensure(frame + 2);
+            if frame + 1 >= self.frames() {
+                break;
+            }
+            let fraction = (self.read_pos - frame as f64) as f32;
+            for channel in 0..self.channels {
+                let current = self.sample(frame, channel);
+                let next = self.sample(frame + 1, channel);
+                self.output.push_back(current + (next - current) * fraction);
+            }
+            self.read_pos += f64::from(speed);
+            produced = true;
+        }
+        produced
+    }
+
+    fn wsola_step(&mut self, speed: f32) -> bool {
+        let ideal = self.read_pos.round() as usize;
+        self.ensure(ideal + self.tolerance + self.hop * 2);
+        if ideal + self.hop * 2 > self.frames() {
+            self.leave_wsola();
+            return self.pass_through();
+        }
+
+        let start = if self.continuation.is_none() {
+            ideal
+        } else {
+            let lowest = ideal.saturating_sub(self.tolerance);
+            let highest = (ideal + self.tolerance).min(self.frames() - self.hop * 2);
+            (lowest..=highest)
+                .step_by(2)
+                .map(|candidate| (candidate, self.similarity(candidate)))
+                .fold((ideal, f32::MIN), |best, candidate| {
+                    if candidate.1 > best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                })
+                .0
+        };
+
+        // Without a previous window to blend with, start at full level so
+        // switching speed does not dip.
+        let blend = self.continuation.is_some();
+        for i in 0..self.hop {
+            for channel in 0..self.channels {
+                let sample = self.sample(start + i, channel);
+                let value = if blend {
+                    sample * self.window[i] + self.overlap[i * self.channels + channel]
+                } else {
+                    sample
+                };
+                self.output.push_back(value);
+            }
+        }
+
+        self.overlap.clear();
+        for i in 0..self.hop {
+            for channel in 0..self.channels {
+                let sample = self.sample(start + self.hop + i, channel);
+                self.overlap.push(sample * self.window[self.hop + i]);
+            }
+        }
+        self.natural = (0..self.hop)
+            .step_by(CORRELATION_STRIDE)
+            .map(|i| self.mono(start + self.hop + i))
+            .collect();
+        self.continuation = Some(start + self.hop);
+        self.read_pos += (self.hop as f32 * speed) as f64;
+        true
+    }
+
+    /// Normalised cross-correlation between the window starting at
+    /// `candidate` and the natural continuation of the last one.
+    fn similarity(&self, candidate: usize) -> f32 {
+        let mut dot = 0.0;
+        let mut energy = 0.0;
+        for (index, target) in self.natural.iter().enumerate() {
+            let sample = self.mono(candidate + index * CORRELATION_STRIDE);
+            dot += sample * target;
+            energy += sample * sample;
+        }
+        dot / energy.max(1e-9).sqrt()
+    }
+
+    /// Carries on from the input that follows the last window, unfaded, so
+    /// leaving WSOLA is seamless.
+    fn leave_wsola(&mut self) {
+        if let Some(continuation) = self.continuation.take() {
+            self.read_pos = continuation as f64;
+            self.overlap.clear();
+            self.natural.clear();
+        }
+    }
+
+    /// Drops input that no window can reach any more.
+    fn compact(&mut self) {
+        let mut first_needed = self.read_pos as usize;
+        if let Some(continuation) = self.continuation {
+            first_needed = first_needed.min(continuation);
+        }
+        let reachable = first_needed.saturating_sub(self.tolerance);
+        if reachable > self.hop * 4 {
+            self.buffer.drain(..reachable * self.channels);
+            self.read_pos -= reachable as f64;
+            if let Some(continuation) = &mut self.continuation {
+                *continuation -= reachable;
+            }
+        }
+    }
+}
+
+impl<S: Source> Iterator for TimeStretch<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.output.is_empty() && !self.fill() {
+            return None;
+        }
+        let sample = self.output.pop_front();
+        // Refill straight away, so the span length and format reported
+        // between samples describe what comes next.
+        if self.output.is_empty() {
+            self.fill();
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.output.len(), None)
+    }
+}
+
+impl<S: Source> Source for TimeStretch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        if self.output.is_empty() {
+            self.input.current_span_len()
+        } else {
+            Some(self.output.len())
+        }
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels as ChannelCount
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// The length of the track itself; how long it takes to play depends on
+    /// the speed, which may change along the way.
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.configure();
+        self.input_done = false;
+        self.held = None;
+        self.output.clear();
+        Ok(())
+    }
+}
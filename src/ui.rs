@@ -1,10 +1,18 @@
 use crate::app::{App, UiMode};
+use crate::bookmarks::Bookmark;
+use crate::eq::{BAND_FREQUENCIES, MAX_GAIN_DB, band_label};
+use crate::filter::fuzzy_match;
+use crate::keymap::Action;
+use crate::library::LibraryItem;
 use crate::player::{MusicPlayer, PlaybackState};
+use crate::queue::RepeatMode;
+use crate::theme::Theme;
 use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Wrap},
 };
 use std::time::Duration;
 
@@ -13,13 +21,18 @@ pub fn render(frame: &mut Frame, app: &App, player: &MusicPlayer) {
         .direction(Direction::Vertical)
         .constraints([Constraint::Min(0), Constraint::Length(3)])
         .split(frame.area());
+    frame.render_widget(Block::default().style(app.theme.base()), frame.area());
 
     match app.ui_mode {
         UiMode::Default => render_default(frame, app, player, vertical_chunks[0]),
         UiMode::FullScreenPlayer => render_full_screen(frame, app, player, vertical_chunks[0]),
+        UiMode::Library => render_library(frame, app, player, vertical_chunks[0]),
+        UiMode::Equalizer => render_equalizer(frame, app, player, vertical_chunks[0]),
     }
 
     render_footer(frame, app, vertical_chunks[1]);
+    render_bookmark_list(frame, app, player);
+    render_help(frame, app);
 }
 
 fn render_default(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
@@ -29,96 +42,316 @@ fn render_default(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect
         .split(area);
 
     render_file_list(frame, app, player, chunks[0]);
-    render_player_panel(frame, player, chunks[1]);
+    render_player_panel(frame, app, player, chunks[1]);
 }
 
-fn render_full_screen(frame: &mut Frame, _app: &App, player: &MusicPlayer, area: Rect) {
-    render_player_panel(frame, player, area);
+fn render_full_screen(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
+    if player.chapters().is_empty() {
+        render_player_panel(frame, app, player, area);
+        return;
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(32), Constraint::Percentage(68)])
+        .split(area);
+
+    render_chapter_list(frame, app, player, chunks[0]);
+    render_player_panel(frame, app, player, chunks[1]);
+}
+
+/// The chapters of the current file, kept scrolled to the one playing.
+fn render_chapter_list(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
+    let chapters = player.chapters();
+    let current = player.current_chapter();
+    let items: Vec<ListItem> = chapters
+        .iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            let item = ListItem::new(format!(
+                "{}  {}",
+                format_duration(chapter.start),
+                chapter.title
+            ));
+            if Some(index) == current {
+                item.style(app.theme.playing())
+            } else {
+                item
+            }
+        })
+        .collect();
+    let title = format!("Chapters ({})", chapters.len());
+    let list = List::new(items)
+        .block(themed_block(title, &app.theme))
+        .highlight_symbol("▶ ");
+    let mut state = ListState::default();
+    state.select(current);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_library(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(32), Constraint::Percentage(68)])
+        .split(area);
+
+    render_library_list(frame, app, player, chunks[0]);
+    render_player_panel(frame, app, player, chunks[1]);
+}
+
+fn render_equalizer(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    render_eq_sliders(frame, app, chunks[0]);
+    render_player_panel(frame, app, player, chunks[1]);
+}
+
+/// One vertical slider per band, filled from the 0 dB line towards the
+/// band's gain, with the gain and frequency underneath.
+fn render_eq_sliders(frame: &mut Frame, app: &App, area: Rect) {
+    let eq = &app.eq;
+    let block = themed_block(format!("Equalizer: {}", eq.name), &app.theme);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    if inner.width == 0 || inner.height < 3 {
+        return;
+    }
+
+    let width = (inner.width as usize / BAND_FREQUENCIES.len()).max(1);
+    let rows = inner.height as usize - 2;
+    let step = 2.0 * MAX_GAIN_DB / rows as f32;
+    let zero_row = rows / 2;
+    let band_style = |band: usize| {
+        if band == eq.selected_band {
+            app.theme.playing()
+        } else {
+            Style::default().fg(app.theme.progress)
+        }
+    };
+
+    let mut lines: Vec<Line> = (0..rows)
+        .map(|row| {
+            let level = MAX_GAIN_DB - (row as f32 + 0.5) * step;
+            let spans = eq
+                .gains
+                .iter()
+                .enumerate()
+                .map(|(band, gain)| {
+                    let filled = (level >= 0.0 && level < *gain) || (level <= 0.0 && level > *gain);
+                    let symbol = if filled {
+                        '█'
+                    } else if row == zero_row {
+                        '─'
+                    } else {
+                        '│'
+                    };
+                    Span::styled(format!("{symbol:^width$}"), band_style(band))
+                })
+                .collect::<Vec<_>>();
+            Line::from(spans)
+        })
+        .collect();
+    lines.push(Line::from(
+        eq.gains
+            .iter()
+            .enumerate()
+            .map(|(band, gain)| Span::styled(format!("{gain:^+width$.0}"), band_style(band)))
+            .collect::<Vec<_>>(),
+    ));
+    lines.push(Line::from(
+        BAND_FREQUENCIES
+            .iter()
+            .enumerate()
+            .map(|(band, frequency)| {
+                Span::styled(
+                    format!("{:^width$}", band_label(*frequency)),
+                    band_style(band),
+                )
+            })
+            .collect::<Vec<_>>(),
+    ));
+
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+fn render_library_list(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
+    let browser = &app.library_browser;
+    let items: Vec<ListItem> = browser
+        .items
+        .iter()
+        .map(|item| {
+            let style = match item {
+                LibraryItem::Track(track) if player.is_playing_track(&track.path, None) => {
+                    app.theme.playing()
+                }
+                _ => Style::default(),
+            };
+            ListItem::new(item.label()).style(style)
+        })
+        .collect();
+
+    let mut title = format!("Library: {}", browser.category.label());
+    for step in &browser.trail {
+        title.push_str(" / ");
+        title.push_str(step);
+    }
+
+    let list = List::new(items)
+        .block(themed_block(title, &app.theme))
+        .highlight_style(app.theme.highlight())
+        .highlight_symbol("> ");
+
+    let mut state = ListState::default();
+    state.select(Some(browser.selected_index));
+    frame.render_stateful_widget(list, area, &mut state);
 }
 
 fn render_file_list(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
-    let items: Vec<ListItem> = app
-        .entries
+    let visible = app.visible_entries();
+    let query = app
+        .filter
+        .as_ref()
+        .map_or("", |filter| filter.query.as_str());
+    let items: Vec<ListItem> = visible
         .iter()
-        .map(|entry| {
-            let icon = if entry.is_dir { "DIR" } else { "MP3" };
-            let style = if player.is_playing_track(&entry.path) {
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD)
+        .map(|&index| {
+            let entry = &app.entries[index];
+            let icon = if entry.is_dir {
+                "DIR"
+            } else if entry.is_playlist {
+                "LST"
+            } else {
+                "MP3"
+            };
+            let style = if player.is_playing_track(&entry.path, entry.span()) {
+                app.theme.playing()
             } else {
                 Style::default()
             };
 
-            ListItem::new(format!("{icon} {}", entry.name)).style(style)
+            let name = app.display_name(entry);
+            let matched = fuzzy_match(query, &name).unwrap_or_default();
+            let mut spans = vec![Span::raw(format!("{icon} "))];
+            spans.extend(name.chars().enumerate().map(|(position, ch)| {
+                if matched.contains(&position) {
+                    Span::styled(ch.to_string(), app.theme.filter_match())
+                } else {
+                    Span::raw(ch.to_string())
+                }
+            }));
+            ListItem::new(Line::from(spans)).style(style)
         })
         .collect();
 
+    let mut title = app
+        .open_playlist
+        .as_ref()
+        .unwrap_or(&app.current_path)
+        .to_string_lossy()
+        .to_string();
+    if let Some(filter) = &app.filter {
+        let cursor = if filter.editing { "_" } else { "" };
+        title.push_str(&format!(
+            " /{}{cursor} ({}/{})",
+            filter.query,
+            filter.matches.len(),
+            app.entries.len()
+        ));
+    }
+
     let list = List::new(items)
-        .block(
-            Block::default()
-                .title(app.current_path.to_string_lossy())
-                .borders(Borders::ALL),
-        )
-        .highlight_style(Style::default().fg(Color::Black).bg(Color::Yellow))
+        .block(themed_block(title, &app.theme))
+        .highlight_style(app.theme.highlight())
         .highlight_symbol("> ");
 
     let mut state = ListState::default();
-    state.select(Some(app.selected_index));
+    state.select(
+        visible
+            .iter()
+            .position(|&index| index == app.selected_index),
+    );
     frame.render_stateful_widget(list, area, &mut state);
 }
 
-fn render_player_panel(frame: &mut Frame, player: &MusicPlayer, area: Rect) {
+fn render_player_panel(frame: &mut Frame, app: &App, player: &MusicPlayer, area: Rect) {
+    let theme = &app.theme;
+    // Room under the song name for the chapter title.
+    let song_height = if player.chapters().is_empty() { 3 } else { 4 };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(5),
+            Constraint::Min(5),
+            Constraint::Length(song_height),
             Constraint::Length(3),
             Constraint::Length(3),
         ])
         .split(area);
 
-    render_cava(frame, player, chunks[0]);
-    render_song_name(frame, player, chunks[1]);
-    render_progress(frame, player, chunks[2]);
+    render_cava(frame, player, theme, chunks[0]);
+    render_song_name(frame, player, theme, chunks[1]);
+    render_progress(frame, player, theme, &app.bookmarks, chunks[2]);
+    render_volume(frame, player, theme, chunks[3]);
 }
 
-fn render_cava(frame: &mut Frame, player: &MusicPlayer, area: Rect) {
-    let block = Block::default().title("Cava").borders(Borders::ALL);
+fn render_cava(frame: &mut Frame, player: &MusicPlayer, theme: &Theme, area: Rect) {
+    let block = themed_block("Cava", theme);
     let inner = block.inner(area);
     frame.render_widget(block, area);
     if inner.width == 0 || inner.height == 0 {
         return;
     }
 
-    let elapsed_ms = player
-        .current_position()
-        .unwrap_or(Duration::ZERO)
-        .as_millis() as f32;
-    let phase = elapsed_ms / 120.0;
-    let mut line = String::with_capacity(inner.width as usize);
-    let levels = [' ', '.', ':', '-', '=', '+', '*', '#'];
-    let active = player.state == PlaybackState::Playing;
+    let bars = player.spectrum();
+    let display_bars = (inner.width as usize).div_ceil(2);
+    let heights: Vec<f32> = (0..display_bars)
+        .map(|index| {
+            let first = index * bars.len() / display_bars;
+            let last = ((index + 1) * bars.len() / display_bars).max(first + 1);
+            bars[first..last.min(bars.len())]
+                .iter()
+                .copied()
+                .fold(0.0, f32::max)
+        })
+        .collect();
+
+    let levels = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let eighths_per_row = (levels.len() - 1) as f32;
+    let rows = inner.height as usize;
+    let mut text = String::with_capacity(rows * (inner.width as usize + 1) * 3);
 
-    for x in 0..inner.width {
-        let t = x as f32 * 0.26 + phase;
-        let wave = if active {
-            ((t.sin() + (t * 0.53 + 1.3).sin() + 2.0) / 4.0).clamp(0.0, 1.0)
-        } else {
-            0.0
-        };
-        let idx = (wave * (levels.len() - 1) as f32).round() as usize;
-        line.push(levels[idx]);
+    for row in (0..rows).rev() {
+        for column in 0..inner.width as usize {
+            if column % 2 == 1 {
+                text.push(' ');
+                continue;
+            }
+            let filled =
+                heights[column / 2] * rows as f32 * eighths_per_row - row as f32 * eighths_per_row;
+            let idx = filled.clamp(0.0, eighths_per_row).round() as usize;
+            text.push(levels[idx]);
+        }
+        if row > 0 {
+            text.push('\n');
+        }
     }
 
-    let paragraph = Paragraph::new(line)
+    let paragraph = Paragraph::new(text)
         .alignment(Alignment::Left)
-        .style(Style::default().fg(Color::Cyan));
+        .style(Style::default().fg(theme.visualizer));
     frame.render_widget(paragraph, inner);
 }
 
-fn render_song_name(frame: &mut Frame, player: &MusicPlayer, area: Rect) {
-    let block = Block::default().title("Now Playing").borders(Borders::ALL);
+fn render_song_name(frame: &mut Frame, player: &MusicPlayer, theme: &Theme, area: Rect) {
+    let queue = player.queue();
+    let block_title = match queue.current_position() {
+        Some(position) if !queue.is_empty() => {
+            format!("Now Playing ({}/{})", position + 1, queue.len())
+        }
+        _ => "Now Playing".to_string(),
+    };
+    let block = themed_block(block_title, theme);
     let inner = block.inner(area);
     frame.render_widget(block, area);
 
@@ -132,14 +365,48 @@ fn render_song_name(frame: &mut Frame, player: &MusicPlayer, area: Rect) {
         PlaybackState::Stopped => "STOP",
     };
 
-    let paragraph = Paragraph::new(format!("[{state_tag}] {title}"))
-        .alignment(Alignment::Center)
-        .style(Style::default().add_modifier(Modifier::BOLD));
+    let mut mode_tags = String::new();
+    if queue.shuffle() {
+        mode_tags.push_str(" [SHUFFLE]");
+    }
+    match queue.repeat() {
+        RepeatMode::Off => {}
+        RepeatMode::One => mode_tags.push_str(" [REPEAT ONE]"),
+        RepeatMode::All => mode_tags.push_str(" [REPEAT ALL]"),
+    }
+
+    let album = player
+        .current_metadata
+        .as_ref()
+        .and_then(|metadata| metadata.album.as_deref())
+        .map(|album| format!(" · {album}"))
+        .unwrap_or_default();
+
+    let mut lines = vec![
+        Line::from(format!("[{state_tag}]{mode_tags} {title}{album}"))
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    ];
+    if let Some(index) = player.current_chapter() {
+        let chapters = player.chapters();
+        lines.push(Line::from(format!(
+            "Chapter {}/{}: {}",
+            index + 1,
+            chapters.len(),
+            chapters[index].title
+        )));
+    }
+    let paragraph = Paragraph::new(lines).alignment(Alignment::Center);
     frame.render_widget(paragraph, inner);
 }
 
-fn render_progress(frame: &mut Frame, player: &MusicPlayer, area: Rect) {
-    let block = Block::default().title("Progress").borders(Borders::ALL);
+fn render_progress(
+    frame: &mut Frame,
+    player: &MusicPlayer,
+    theme: &Theme,
+    bookmarks: &[Bookmark],
+    area: Rect,
+) {
+    let block = themed_block("Progress", theme);
     let inner = block.inner(area);
     frame.render_widget(block, area);
 
@@ -147,37 +414,228 @@ fn render_progress(frame: &mut Frame, player: &MusicPlayer, area: Rect) {
     let duration = player.current_duration();
     let elapsed = format_duration(position);
 
-    let (ratio, label) = match duration {
+    let speed = player.speed();
+    let (ratio, mut label) = match duration {
         Some(total) if total.as_secs_f64() > 0.0 => {
             let progress = (position.as_secs_f64() / total.as_secs_f64()).clamp(0.0, 1.0);
             (progress, format!("{elapsed} / {}", format_duration(total)))
         }
         _ => (0.0, format!("{elapsed} / --:--")),
     };
+    if speed != 1.0 {
+        label.push_str(&format!(" · {speed:.1}x"));
+        if let Some(total) = duration {
+            let left = total.saturating_sub(position).div_f32(speed);
+            label.push_str(&format!(", {} left", format_duration(left)));
+        }
+    }
 
+    let label_width = label.chars().count() as u16;
     let gauge = Gauge::default()
         .ratio(ratio)
         .label(label)
-        .gauge_style(Style::default().fg(Color::LightBlue).bg(Color::DarkGray));
+        .gauge_style(theme.gauge(theme.progress));
+    frame.render_widget(gauge, inner);
+
+    if let Some(total) = duration.filter(|total| !total.is_zero()) {
+        render_bookmark_markers(frame, bookmarks, total, ratio, label_width, theme, inner);
+    }
+}
+
+/// A tick on the progress gauge for each bookmark, left out where it would
+/// cover the label.
+fn render_bookmark_markers(
+    frame: &mut Frame,
+    bookmarks: &[Bookmark],
+    total: Duration,
+    ratio: f64,
+    label_width: u16,
+    theme: &Theme,
+    area: Rect,
+) {
+    if area.is_empty() {
+        return;
+    }
+    let label_width = label_width.min(area.width);
+    let label_start = area.left() + (area.width - label_width) / 2;
+    let label_row = area.top() + area.height / 2;
+    let filled_end = area.left() + (f64::from(area.width) * ratio).round() as u16;
+    for bookmark in bookmarks {
+        let fraction = (bookmark.position.as_secs_f64() / total.as_secs_f64()).clamp(0.0, 1.0);
+        let x = area.left() + ((f64::from(area.width) - 1.0) * fraction).round() as u16;
+        let background = if x < filled_end {
+            theme.progress
+        } else {
+            theme.gauge_background
+        };
+        for y in area.top()..area.bottom() {
+            if y == label_row && (label_start..label_start + label_width).contains(&x) {
+                continue;
+            }
+            frame.buffer_mut()[(x, y)]
+                .set_symbol("│")
+                .set_fg(theme.playing)
+                .set_bg(background);
+        }
+    }
+}
+
+/// The playing track's bookmarks, centred over the rest of the screen.
+fn render_bookmark_list(frame: &mut Frame, app: &App, player: &MusicPlayer) {
+    let Some(selected) = app.bookmark_selection else {
+        return;
+    };
+    let screen = frame.area();
+    let width = (screen.width * 3 / 5).max(30).min(screen.width);
+    let height = (app.bookmarks.len() as u16 + 2).clamp(3, screen.height.saturating_sub(4).max(3));
+    let area = Rect {
+        x: screen.x + (screen.width - width) / 2,
+        y: screen.y + screen.height.saturating_sub(height) / 2,
+        width,
+        height: height.min(screen.height),
+    };
+
+    let title = match &player.current_song_name {
+        Some(name) => format!("Bookmarks · {name}"),
+        None => "Bookmarks".to_string(),
+    };
+    let block = themed_block(title, &app.theme).style(app.theme.base());
+    frame.render_widget(Clear, area);
+    if app.bookmarks.is_empty() {
+        let hint = match app.keymap.keys_for(Action::AddBookmark) {
+            Some(keys) => format!("No bookmarks yet; {keys} adds one."),
+            None => "No bookmarks yet.".to_string(),
+        };
+        frame.render_widget(Paragraph::new(hint).block(block), area);
+        return;
+    }
+
+    let items: Vec<ListItem> = app
+        .bookmarks
+        .iter()
+        .map(|bookmark| {
+            ListItem::new(format!(
+                "{}  {}",
+                format_duration(bookmark.position),
+                bookmark.name
+            ))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(app.theme.highlight())
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select(Some(selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Every key binding, in as many columns as fit, over the middle of the
+/// screen.
+fn render_help(frame: &mut Frame, app: &App) {
+    if !app.show_help {
+        return;
+    }
+    let entries = app.keymap.help_entries();
+    let screen = frame.area();
+    let width = (screen.width * 4 / 5).max(30).min(screen.width);
+    let column_width = entries
+        .iter()
+        .map(|entry| entry.chars().count() + 2)
+        .max()
+        .unwrap_or(1);
+    let columns = (usize::from(width.saturating_sub(2)) / column_width).max(1);
+    let rows = entries.len().div_ceil(columns);
+    let height = (rows as u16 + 2).min(screen.height);
+    let area = Rect {
+        x: screen.x + (screen.width - width) / 2,
+        y: screen.y + (screen.height - height) / 2,
+        width,
+        height,
+    };
+
+    let lines: Vec<Line> = (0..rows)
+        .map(|row| {
+            let text: String = (0..columns)
+                .filter_map(|column| entries.get(column * rows + row))
+                .map(|entry| format!("{entry:<column_width$}"))
+                .collect();
+            Line::from(text.trim_end().to_string())
+        })
+        .collect();
+    let title = match app.keymap.keys_for(Action::Help) {
+        Some(keys) => format!("Keys ({keys} or Esc closes)"),
+        None => "Keys (Esc closes)".to_string(),
+    };
+    let block = themed_block(title, &app.theme).style(app.theme.base());
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn render_volume(frame: &mut Frame, player: &MusicPlayer, theme: &Theme, area: Rect) {
+    let title = match player.current_gain() {
+        Some(gain) => format!(
+            "Volume · ReplayGain {:+.1} dB ({}{})",
+            gain.db,
+            gain.source.name(),
+            if gain.limited { ", peak-limited" } else { "" }
+        ),
+        None => "Volume".to_string(),
+    };
+    let block = themed_block(title, theme);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let volume = f64::from(player.volume());
+    let (ratio, label, color) = if player.is_muted() {
+        (0.0, "Muted".to_string(), theme.muted)
+    } else {
+        (volume, format!("{:.0}%", volume * 100.0), theme.volume)
+    };
+
+    let gauge = Gauge::default()
+        .ratio(ratio)
+        .label(label)
+        .gauge_style(theme.gauge(color));
     frame.render_widget(gauge, inner);
 }
 
 fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
-    let mut text = String::from(
-        "Up/Down: Navigate | Enter: Open/Play/Pause | 1: Split | 2: Player | Esc: Quit",
-    );
-    if let Some(status) = &app.status {
-        text.push_str(" | ");
-        text.push_str(status);
+    if let Some(prompt) = &app.bookmark_prompt {
+        let text = format!(
+            "Bookmark at {} named: {}_ (Enter saves, Esc cancels)",
+            format_duration(prompt.position),
+            prompt.name
+        );
+        let paragraph = Paragraph::new(text).block(themed_block("Bookmark", &app.theme));
+        frame.render_widget(paragraph, area);
+        return;
     }
 
-    let paragraph = Paragraph::new(text)
-        .block(Block::default().title("Keys").borders(Borders::ALL))
+    // Status first, so the short help is what gets cut off when space runs
+    // out.
+    let mut parts = Vec::new();
+    if let Some(pending) = app.keymap.pending_text() {
+        parts.push(format!("{pending} …"));
+    }
+    parts.extend(app.library_status());
+    parts.extend(app.status.clone());
+    parts.push(app.keymap.short_help());
+
+    let paragraph = Paragraph::new(parts.join(" | "))
+        .block(themed_block("Keys", &app.theme))
         .wrap(Wrap { trim: true });
     frame.render_widget(paragraph, area);
 }
 
-fn format_duration(duration: Duration) -> String {
+fn themed_block<'a>(title: impl Into<Line<'a>>, theme: &Theme) -> Block<'a> {
+    Block::default()
+        .title(title.into().style(theme.title()))
+        .borders(Borders::ALL)
+        .border_style(theme.border())
+}
+
+pub fn format_duration(duration: Duration) -> String {
     let secs = duration.as_secs();
     format!("{:02}:{:02}", secs / 60, secs % 60)
 }
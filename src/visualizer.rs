@@ -0,0 +1,241 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+const BAR_COUNT: usize = 64;
+
+const RING_CAPACITY: usize = 8192;
+const FFT_SIZE: usize = 2048;
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16_000.0;
+const DB_FLOOR: f32 = -70.0;
+const RISE_FACTOR: f32 = 0.6;
+const FALLOFF_PER_SEC: f32 = 1.8;
+
+/// Mono downmix of the most recently decoded samples. Written from the audio
+/// thread and read from the UI thread without locking; a reader racing a
+/// writer may see a few stale samples, which is harmless for display.
+///
+/// Only one tap publishes at a time: the one that most recently started
+/// producing audio, so an outgoing track in a crossfade goes quiet here.
+pub struct SampleRing {
+    samples: Box<[AtomicU32]>,
+    written: AtomicUsize,
+    sample_rate: AtomicU32,
+    next_tap: AtomicUsize,
+    active_tap: AtomicUsize,
+}
+
+impl SampleRing {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            samples: (0..RING_CAPACITY).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            sample_rate: AtomicU32::new(0),
+            next_tap: AtomicUsize::new(1),
+            active_tap: AtomicUsize::new(0),
+        })
+    }
+
+    fn push(&self, sample: f32) {
+        let index = self.written.load(Ordering::Relaxed);
+        self.samples[index % RING_CAPACITY].store(sample.to_bits(), Ordering::Relaxed);
+        self.written.store(index.wrapping_add(1), Ordering::Release);
+    }
+
+    fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Copies the newest `out.len()` samples into `out`, oldest first.
+    fn copy_latest(&self, out: &mut [f32]) {
+        let end = self.written();
+        let start = end.wrapping_sub(out.len());
+        for (offset, slot) in out.iter_mut().enumerate() {
+            let index = start.wrapping_add(offset) % RING_CAPACITY;
+            *slot = f32::from_bits(self.samples[index].load(Ordering::Relaxed));
+        }
+    }
+}
+
+/// Passes samples through unchanged while publishing a mono copy to a
+/// [`SampleRing`].
+pub struct SampleTap<S> {
+    input: S,
+    ring: Arc<SampleRing>,
+    id: usize,
+    claimed: bool,
+    frame_sum: f32,
+    frame_len: ChannelCount,
+}
+
+impl<S: Source> SampleTap<S> {
+    pub fn new(input: S, ring: Arc<SampleRing>) -> Self {
+        let id = ring.next_tap.fetch_add(1, Ordering::Relaxed);
+        Self {
+            input,
+            ring,
+            id,
+            claimed: false,
+            frame_sum: 0.0,
+            frame_len: 0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for SampleTap<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        if !self.claimed {
+            self.ring.active_tap.store(self.id, Ordering::Relaxed);
+            self.claimed = true;
+        }
+        self.frame_sum += sample;
+        self.frame_len += 1;
+
+        let channels = self.input.channels().max(1);
+        if self.frame_len >= channels {
+            if self.ring.active_tap.load(Ordering::Relaxed) == self.id {
+                // Stored per frame rather than up front: a preloaded next
+                // track is built while the current one is still playing.
+                self.ring
+                    .sample_rate
+                    .store(self.input.sample_rate(), Ordering::Relaxed);
+                self.ring.push(self.frame_sum / f32::from(channels));
+            }
+            self.frame_sum = 0.0;
+            self.frame_len = 0;
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for SampleTap<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.frame_sum = 0.0;
+        self.frame_len = 0;
+        self.input.try_seek(pos)
+    }
+}
+
+/// Turns the contents of a [`SampleRing`] into `BAR_COUNT` log-spaced bar
+/// levels between 0.0 and 1.0.
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    samples: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    bars: Vec<f32>,
+    last_written: usize,
+    last_update: Instant,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+
+        Self {
+            fft,
+            window,
+            samples: vec![0.0; FFT_SIZE],
+            buffer: vec![Complex::default(); FFT_SIZE],
+            scratch,
+            bars: vec![0.0; BAR_COUNT],
+            last_written: 0,
+            last_update: Instant::now(),
+        }
+    }
+
+    pub fn bars(&self) -> &[f32] {
+        &self.bars
+    }
+
+    /// Recomputes the bars from new samples. While `active` is false, or no
+    /// new audio arrived, the bars only fall towards zero.
+    pub fn update(&mut self, ring: &SampleRing, active: bool) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+        let falloff = FALLOFF_PER_SEC * elapsed;
+
+        let written = ring.written();
+        let sample_rate = ring.sample_rate();
+        if !active || written == self.last_written || sample_rate == 0 {
+            for bar in &mut self.bars {
+                *bar = (*bar - falloff).max(0.0);
+            }
+            return;
+        }
+        self.last_written = written;
+
+        ring.copy_latest(&mut self.samples);
+        for ((slot, sample), weight) in self.buffer.iter_mut().zip(&self.samples).zip(&self.window)
+        {
+            *slot = Complex::new(sample * weight, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let max_frequency = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let ratio = max_frequency / MIN_FREQUENCY;
+        let normalization = FFT_SIZE as f32 / 4.0;
+
+        for (index, bar) in self.bars.iter_mut().enumerate() {
+            let low = MIN_FREQUENCY * ratio.powf(index as f32 / BAR_COUNT as f32);
+            let high = MIN_FREQUENCY * ratio.powf((index + 1) as f32 / BAR_COUNT as f32);
+            let first_bin = ((low / bin_width) as usize).clamp(1, FFT_SIZE / 2 - 1);
+            let last_bin = ((high / bin_width) as usize).clamp(first_bin + 1, FFT_SIZE / 2);
+
+            let magnitude = self.buffer[first_bin..last_bin]
+                .iter()
+                .map(|value| value.norm())
+                .fold(0.0_f32, f32::max);
+            let db = 20.0 * (magnitude / normalization).max(1e-9).log10();
+            let level = ((db - DB_FLOOR) / -DB_FLOOR).clamp(0.0, 1.0);
+
+            *bar = if level > *bar {
+                *bar + (level - *bar) * RISE_FACTOR
+            } else {
+                level.max(*bar - falloff)
+            };
+        }
+    }
+}